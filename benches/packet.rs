@@ -0,0 +1,161 @@
+use std::hint::black_box;
+use std::net::Ipv4Addr;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use dns_server::packet::{DnsPacket, PacketBuffer};
+use dns_server::query_type::QueryType;
+use dns_server::question::DnsQuestion;
+use dns_server::record::{DnsRecord, CLASS_IN};
+
+/// Response shaped like a typical recursive answer: a CNAME to a name with several A records,
+/// nameservers in the authority section and their glue in the additional section
+fn realistic_response() -> DnsPacket {
+    let mut packet = DnsPacket::new();
+    packet.header.id = 0x862a;
+    packet.header.response = true;
+    packet.header.recursion_desired = true;
+    packet.header.recursion_available = true;
+    packet.questions.push(DnsQuestion::new(
+        "www.example.com".to_string(),
+        QueryType::A,
+    ));
+    packet.answers.push(DnsRecord::CNAME {
+        domain: "www.example.com".to_string(),
+        host: "example.edgesuite.net".to_string(),
+        class: CLASS_IN,
+        ttl: 300,
+    });
+    for octet in 1..=4 {
+        packet.answers.push(DnsRecord::A {
+            domain: "example.edgesuite.net".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, octet),
+            class: CLASS_IN,
+            ttl: 20,
+        });
+    }
+    for (host, octet) in [("ns1.edgesuite.net", 53), ("ns2.edgesuite.net", 54)] {
+        packet.authorities.push(DnsRecord::NS {
+            domain: "edgesuite.net".to_string(),
+            host: host.to_string(),
+            class: CLASS_IN,
+            ttl: 172800,
+        });
+        packet.resources.push(DnsRecord::A {
+            domain: host.to_string(),
+            addr: Ipv4Addr::new(198, 51, 100, octet),
+            class: CLASS_IN,
+            ttl: 172800,
+        });
+    }
+    packet
+}
+
+fn packet_bytes(packet: &DnsPacket) -> ([u8; 512], usize) {
+    let mut buffer = PacketBuffer::new([0; 512]);
+    packet.write(&mut buffer).unwrap();
+    let len = buffer.pos();
+    let mut buf = [0; 512];
+    buf[..len].copy_from_slice(buffer.get_range(0, len).unwrap());
+    (buf, len)
+}
+
+fn parse_packet(c: &mut Criterion) {
+    let (buf, len) = packet_bytes(&realistic_response());
+    let mut group = c.benchmark_group("packet");
+    group.throughput(Throughput::Bytes(len as u64));
+    group.bench_function("from_buffer", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::new(black_box(buf));
+            DnsPacket::from_buffer(&mut buffer).unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn reject_junk(c: &mut Criterion) {
+    // Stray HTTP traffic, whose header counts claim far more entries than the datagram holds
+    let mut buf = [0; 512];
+    let junk = b"GET /metrics HTTP/1.1\r\nHost: 192.0.2.1:8080\r\nAccept: */*\r\n\r\n";
+    buf[..junk.len()].copy_from_slice(junk);
+
+    let mut group = c.benchmark_group("reject_junk");
+    group.throughput(Throughput::Bytes(junk.len() as u64));
+    group.bench_function("is_plausible", |b| {
+        b.iter(|| DnsPacket::is_plausible(black_box(&junk[..])))
+    });
+    group.bench_function("from_buffer", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::new(black_box(buf));
+            DnsPacket::from_buffer(&mut buffer).is_ok()
+        })
+    });
+    group.finish();
+}
+
+fn write_packet(c: &mut Criterion) {
+    let packet = realistic_response();
+    let (_, len) = packet_bytes(&packet);
+    let mut group = c.benchmark_group("packet");
+    group.throughput(Throughput::Bytes(len as u64));
+    group.bench_function("write", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::new([0; 512]);
+            black_box(&packet).write(&mut buffer).unwrap();
+            buffer
+        })
+    });
+    group.bench_function("write_compressed", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::new([0; 512]);
+            black_box(&packet).write_compressed(&mut buffer).unwrap();
+            buffer
+        })
+    });
+    group.finish();
+}
+
+fn read_compressed_qname(c: &mut Criterion) {
+    // Each name is a single label followed by a pointer to the previous name, so reading the
+    // last one follows the maximum number of jumps allowed by default
+    let mut buf = [0; 512];
+    let mut pos = 12;
+    buf[pos..pos + 5].copy_from_slice(&[3, b'c', b'o', b'm', 0]);
+    let mut prev = pos;
+    pos += 5;
+    for label in [b"example", b"subzone", b"service", b"cluster", b"backend"] {
+        buf[pos] = label.len() as u8;
+        buf[pos + 1..pos + 1 + label.len()].copy_from_slice(label);
+        buf[pos + 1 + label.len()] = 0xC0;
+        buf[pos + 2 + label.len()] = prev as u8;
+        prev = pos;
+        pos += label.len() + 3;
+    }
+
+    let mut group = c.benchmark_group("read_qname");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("compressed", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::new(black_box(buf));
+            buffer.seek(prev).unwrap();
+            buffer.read_qname().unwrap()
+        })
+    });
+    let mut qname = String::new();
+    group.bench_function("compressed_into", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::new(black_box(buf));
+            buffer.seek(prev).unwrap();
+            buffer.read_qname_into(&mut qname).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    parse_packet,
+    reject_junk,
+    write_packet,
+    read_compressed_qname
+);
+criterion_main!(benches);
@@ -0,0 +1,450 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::header::ResultCode;
+use crate::packet::{DnsPacket, PacketBuffer, RandomTxIds, TxIdSource};
+use crate::query_type::QueryType;
+use crate::question::DnsQuestion;
+use crate::record::DnsRecord;
+use crate::tsig::{TsigChain, TsigKey};
+
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest message that its two byte length prefix can describe over TCP
+const TCP_MESSAGE_MAX_LENGTH: usize = u16::MAX as usize;
+
+/// Transfer a zone from its primary, returning its records starting with the SOA. The
+/// repeated SOA that marks the end of the transfer isn't included.
+pub fn axfr(primary: SocketAddr, zone: &str) -> Result<Vec<DnsRecord>, String> {
+    transfer(primary, zone, None, &mut RandomTxIds)
+}
+
+/// Transfer a zone as with `axfr`, signing the query with `key` and rejecting the transfer
+/// unless every message from the primary carries a valid TSIG for it.
+pub fn axfr_with_tsig(
+    primary: SocketAddr,
+    zone: &str,
+    key: &TsigKey,
+) -> Result<Vec<DnsRecord>, String> {
+    transfer(primary, zone, Some(key), &mut RandomTxIds)
+}
+
+/// Whether SOA serial `a` is newer than `b` under RFC 1982 serial number arithmetic, so a
+/// serial that has wrapped past `u32::MAX` still counts as newer. Serials exactly 2^31 apart
+/// have no defined order and neither is considered newer.
+pub fn serial_newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+fn transfer(
+    primary: SocketAddr,
+    zone: &str,
+    key: Option<&TsigKey>,
+    ids: &mut impl TxIdSource,
+) -> Result<Vec<DnsRecord>, String> {
+    let mut stream = match TcpStream::connect_timeout(&primary, TCP_TIMEOUT) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let err_str = format!(
+                "Failed to connect to primary: primary={}, error={}",
+                primary, e
+            );
+            return Err(err_str);
+        }
+    };
+    if let Err(e) = stream.set_read_timeout(Some(TCP_TIMEOUT)) {
+        return Err(format!("Failed to set read timeout: {}", e));
+    }
+
+    let mut query = DnsPacket::new();
+    query.header.id = ids.next();
+    query
+        .questions
+        .push(DnsQuestion::new(zone.to_string(), QueryType::AXFR));
+    let mut prior_mac = match key {
+        Some(key) => Some(key.sign(&mut query, unix_time()?, TsigChain::Request)?),
+        None => None,
+    };
+    write_tcp_message(&mut stream, &query)?;
+
+    let mut records: Vec<DnsRecord> = Vec::new();
+    let mut first_message = true;
+    loop {
+        let message = read_tcp_bytes(&mut stream)?;
+        if let (Some(key), Some(mac)) = (key, &prior_mac) {
+            let chain = if first_message {
+                TsigChain::Response(mac)
+            } else {
+                TsigChain::Continuation(mac)
+            };
+            prior_mac = Some(key.verify(&message, unix_time()?, chain)?);
+        }
+        first_message = false;
+        let response = parse_message(&message)?;
+        if !response.header.response {
+            let err_str = format!(
+                "Invalid zone transfer, message from primary isn't a response: id={}",
+                response.header.id
+            );
+            return Err(err_str);
+        }
+        if response.header.id != query.header.id {
+            let err_str = format!(
+                "Invalid zone transfer, response id doesn't match query: query id={}, response id={}",
+                query.header.id, response.header.id
+            );
+            return Err(err_str);
+        }
+        if response.header.rescode != ResultCode::NoError {
+            let err_str = format!(
+                "Zone transfer failed: zone={}, rescode={}",
+                zone, response.header.rescode
+            );
+            return Err(err_str);
+        }
+
+        for record in response.answers {
+            let is_soa = matches!(record, DnsRecord::SOA { .. });
+            if records.is_empty() && !is_soa {
+                let err_str = format!(
+                    "Invalid zone transfer, first record isn't an SOA: zone={}",
+                    zone
+                );
+                return Err(err_str);
+            }
+            if is_soa && !records.is_empty() {
+                return Ok(records);
+            }
+            records.push(record);
+        }
+    }
+}
+
+/// Write a packet prefixed with its two byte length, as messages are framed over TCP
+pub(crate) fn write_tcp_message<W: Write>(
+    writer: &mut W,
+    packet: &DnsPacket,
+) -> Result<(), String> {
+    let mut buffer = PacketBuffer::with_capacity(TCP_MESSAGE_MAX_LENGTH);
+    packet.write(&mut buffer)?;
+    let len = buffer.pos();
+
+    let mut message = (len as u16).to_be_bytes().to_vec();
+    message.extend_from_slice(buffer.get_range(0, len)?);
+    if let Err(e) = writer.write_all(&message) {
+        return Err(format!("Failed to write message: {}", e));
+    }
+    Ok(())
+}
+
+/// Read the bytes of a message prefixed with its two byte length, as messages are framed over
+/// TCP. The raw bytes are kept so that a TSIG can be verified over them.
+fn read_tcp_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, String> {
+    let mut len = [0; 2];
+    if let Err(e) = reader.read_exact(&mut len) {
+        return Err(format!("Failed to read message length: {}", e));
+    }
+    let len = u16::from_be_bytes(len) as usize;
+    let mut message = vec![0; len];
+    if let Err(e) = reader.read_exact(&mut message) {
+        return Err(format!("Failed to read message: {}", e));
+    }
+    Ok(message)
+}
+
+fn parse_message(message: &[u8]) -> Result<DnsPacket, String> {
+    let mut buffer = PacketBuffer::from_message(message);
+    DnsPacket::from_buffer(&mut buffer)
+}
+
+fn unix_time() -> Result<u64, String> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => Ok(time.as_secs()),
+        Err(e) => Err(format!("System clock is before the Unix epoch: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{SequentialTxIds, PACKET_BYTES_LENGTH};
+    use crate::record::CLASS_IN;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    fn soa_record() -> DnsRecord {
+        DnsRecord::SOA {
+            domain: "example.com".to_string(),
+            m_name: "ns1.example.com".to_string(),
+            r_name: "hostmaster.example.com".to_string(),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+            class: CLASS_IN,
+            ttl: 3600,
+        }
+    }
+
+    fn zone_records() -> Vec<DnsRecord> {
+        vec![
+            DnsRecord::NS {
+                domain: "example.com".to_string(),
+                host: "ns1.example.com".to_string(),
+                class: CLASS_IN,
+                ttl: 3600,
+            },
+            DnsRecord::A {
+                domain: "ns1.example.com".to_string(),
+                addr: Ipv4Addr::new(192, 0, 2, 53),
+                class: CLASS_IN,
+                ttl: 3600,
+            },
+            DnsRecord::A {
+                domain: "www.example.com".to_string(),
+                addr: Ipv4Addr::new(192, 0, 2, 80),
+                class: CLASS_IN,
+                ttl: 300,
+            },
+        ]
+    }
+
+    fn tsig_key() -> TsigKey {
+        TsigKey::new("transfer-key".to_string(), b"0123456789abcdef".to_vec())
+    }
+
+    /// Serve a single AXFR request, replying with each group of answers as its own message
+    fn spawn_mock_primary(
+        messages: Vec<Vec<DnsRecord>>,
+        rescode: ResultCode,
+    ) -> (SocketAddr, thread::JoinHandle<DnsPacket>) {
+        spawn_signing_primary(messages, rescode, None)
+    }
+
+    /// Serve a single AXFR request as with `spawn_mock_primary`, signing each message with `key`
+    /// when given one
+    fn spawn_signing_primary(
+        messages: Vec<Vec<DnsRecord>>,
+        rescode: ResultCode,
+        key: Option<TsigKey>,
+    ) -> (SocketAddr, thread::JoinHandle<DnsPacket>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let message = read_tcp_bytes(&mut stream).unwrap();
+            let query = parse_message(&message).unwrap();
+            let mut prior_mac = key.as_ref().map(|key| {
+                key.verify(&message, unix_time().unwrap(), TsigChain::Request)
+                    .unwrap()
+            });
+            for (i, answers) in messages.into_iter().enumerate() {
+                let mut response = DnsPacket::new();
+                response.header.id = query.header.id;
+                response.header.response = true;
+                response.header.authoritative_answer = true;
+                response.header.rescode = rescode;
+                response.questions = query.questions.clone();
+                response.answers = answers;
+                if let (Some(key), Some(mac)) = (&key, &prior_mac) {
+                    let chain = if i == 0 {
+                        TsigChain::Response(mac)
+                    } else {
+                        TsigChain::Continuation(mac)
+                    };
+                    prior_mac = Some(
+                        key.sign(&mut response, unix_time().unwrap(), chain)
+                            .unwrap(),
+                    );
+                }
+                write_tcp_message(&mut stream, &response).unwrap();
+            }
+            query
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn zone_accumulated_across_messages_until_closing_soa() {
+        let records = zone_records();
+        let messages = vec![
+            vec![soa_record(), records[0].clone(), records[1].clone()],
+            vec![records[2].clone(), soa_record()],
+        ];
+        let (addr, handle) = spawn_mock_primary(messages, ResultCode::NoError);
+
+        let res = axfr(addr, "example.com").unwrap();
+        let query = handle.join().unwrap();
+
+        let mut expected_records = vec![soa_record()];
+        expected_records.extend(records);
+        assert_eq!(expected_records, res);
+        assert_eq!(
+            vec![DnsQuestion::new("example.com".to_string(), QueryType::AXFR)],
+            query.questions
+        );
+    }
+
+    #[test]
+    fn return_error_if_primary_refuses_transfer() {
+        let (addr, handle) = spawn_mock_primary(vec![vec![]], ResultCode::Refused);
+        let res = axfr(addr, "example.com");
+        handle.join().unwrap();
+        let expected_str = "Zone transfer failed: zone=example.com, rescode=REFUSED";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_first_record_is_not_soa() {
+        let (addr, handle) = spawn_mock_primary(vec![zone_records()], ResultCode::NoError);
+        let res = axfr(addr, "example.com");
+        handle.join().unwrap();
+        let expected_str = "Invalid zone transfer, first record isn't an SOA: zone=example.com";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_primary_closes_before_closing_soa() {
+        let messages = vec![vec![soa_record(), zone_records()[0].clone()]];
+        let (addr, handle) = spawn_mock_primary(messages, ResultCode::NoError);
+        let res = axfr(addr, "example.com");
+        handle.join().unwrap();
+        let expected_str = "Failed to read message length: failed to fill whole buffer";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn zone_accepted_from_message_longer_than_udp_packet() {
+        let hosts: Vec<DnsRecord> = (1..=40)
+            .map(|octet| DnsRecord::A {
+                domain: format!("host{}.example.com", octet),
+                addr: Ipv4Addr::new(192, 0, 2, octet),
+                class: CLASS_IN,
+                ttl: 300,
+            })
+            .collect();
+        let mut answers = vec![soa_record()];
+        answers.extend(hosts.clone());
+        answers.push(soa_record());
+        let mut message = DnsPacket::new();
+        message.answers = answers.clone();
+        let mut buffer = PacketBuffer::with_capacity(TCP_MESSAGE_MAX_LENGTH);
+        message.write(&mut buffer).unwrap();
+        assert_eq!(true, buffer.pos() > PACKET_BYTES_LENGTH);
+
+        let (addr, handle) = spawn_mock_primary(vec![answers], ResultCode::NoError);
+        let res = axfr(addr, "example.com").unwrap();
+        handle.join().unwrap();
+
+        let mut expected_records = vec![soa_record()];
+        expected_records.extend(hosts);
+        assert_eq!(expected_records, res);
+    }
+
+    #[test]
+    fn return_error_if_primary_replies_with_query() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let query = parse_message(&read_tcp_bytes(&mut stream).unwrap()).unwrap();
+            write_tcp_message(&mut stream, &query).unwrap();
+            query
+        });
+        let res = axfr(addr, "example.com");
+        let query = handle.join().unwrap();
+        let expected_str = format!(
+            "Invalid zone transfer, message from primary isn't a response: id={}",
+            query.header.id
+        );
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn signed_zone_transfer_accepted_with_matching_key() {
+        let records = zone_records();
+        let messages = vec![
+            vec![soa_record(), records[0].clone()],
+            vec![records[1].clone(), records[2].clone(), soa_record()],
+        ];
+        let (addr, handle) = spawn_signing_primary(messages, ResultCode::NoError, Some(tsig_key()));
+
+        let res = axfr_with_tsig(addr, "example.com", &tsig_key()).unwrap();
+        handle.join().unwrap();
+
+        let mut expected_records = vec![soa_record()];
+        expected_records.extend(records);
+        assert_eq!(expected_records, res);
+    }
+
+    #[test]
+    fn return_error_if_transfer_unsigned_when_key_configured() {
+        let messages = vec![vec![soa_record(), soa_record()]];
+        let (addr, handle) = spawn_mock_primary(messages, ResultCode::NoError);
+        let res = axfr_with_tsig(addr, "example.com", &tsig_key());
+        handle.join().unwrap();
+        let expected_str = "Invalid TSIG, message isn't signed";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_transfer_signed_with_different_key() {
+        let wrong_key = TsigKey::new("transfer-key".to_string(), b"not the secret".to_vec());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let query = parse_message(&read_tcp_bytes(&mut stream).unwrap()).unwrap();
+            let mut response = DnsPacket::new();
+            response.header.id = query.header.id;
+            response.header.response = true;
+            response.answers = vec![soa_record(), soa_record()];
+            wrong_key
+                .sign(&mut response, unix_time().unwrap(), TsigChain::Request)
+                .unwrap();
+            write_tcp_message(&mut stream, &response).unwrap();
+        });
+        let res = axfr_with_tsig(addr, "example.com", &tsig_key());
+        handle.join().unwrap();
+        let expected_str = "Invalid TSIG, MAC doesn't match: key=transfer-key";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn query_id_taken_from_id_source() {
+        let (addr, handle) =
+            spawn_mock_primary(vec![vec![soa_record(), soa_record()]], ResultCode::NoError);
+        let mut ids = SequentialTxIds::starting_at(7);
+        let res = transfer(addr, "example.com", None, &mut ids);
+        let query = handle.join().unwrap();
+        assert_eq!(true, res.is_ok());
+        assert_eq!(7, query.header.id);
+    }
+
+    #[test]
+    fn serial_newer_for_larger_serial() {
+        assert_eq!(true, serial_newer(2024010102, 2024010101));
+        assert_eq!(false, serial_newer(2024010101, 2024010102));
+    }
+
+    #[test]
+    fn equal_serials_not_newer() {
+        assert_eq!(false, serial_newer(2024010101, 2024010101));
+    }
+
+    #[test]
+    fn serial_newer_across_wraparound() {
+        assert_eq!(true, serial_newer(0, u32::MAX));
+        assert_eq!(true, serial_newer(5, u32::MAX - 5));
+        assert_eq!(false, serial_newer(u32::MAX, 0));
+    }
+
+    #[test]
+    fn serials_half_sequence_space_apart_unordered() {
+        let half = 1 << 31;
+        assert_eq!(false, serial_newer(half, 0));
+        assert_eq!(false, serial_newer(0, half));
+        assert_eq!(true, serial_newer(half - 1, 0));
+    }
+}
@@ -0,0 +1,1033 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::packet::{DnsPacket, PacketBuffer, PACKET_BYTES_LENGTH};
+use crate::query_type::QueryType;
+use crate::record::DnsRecord;
+
+const OPTION_CODE_NSID: u16 = 3;
+const OPTION_CODE_CLIENT_SUBNET: u16 = 8;
+const OPTION_CODE_COOKIE: u16 = 10;
+const OPTION_CODE_PADDING: u16 = 12;
+const OPTION_CODE_EXTENDED_ERROR: u16 = 15;
+const CLIENT_COOKIE_LEN: usize = 8;
+const MIN_SERVER_COOKIE_LEN: usize = 8;
+const MAX_SERVER_COOKIE_LEN: usize = 32;
+const ADDRESS_FAMILY_IPV4: u16 = 1;
+const ADDRESS_FAMILY_IPV6: u16 = 2;
+/// DNSSEC OK bit within the OPT record flags (RFC 3225)
+const FLAG_DNSSEC_OK: u32 = 1 << 15;
+/// Block size queries are padded to, as recommended by RFC 8467
+pub const QUERY_PADDING_BLOCK_SIZE: usize = 128;
+
+/// Extended DNS error info codes (RFC 8914 section 4) for the failures this server reports
+pub const INFO_CODE_OTHER: u16 = 0;
+pub const INFO_CODE_DNSSEC_BOGUS: u16 = 6;
+pub const INFO_CODE_BLOCKED: u16 = 15;
+pub const INFO_CODE_CENSORED: u16 = 16;
+pub const INFO_CODE_FILTERED: u16 = 17;
+pub const INFO_CODE_PROHIBITED: u16 = 18;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EdnsOption {
+    /// Name server identifier (RFC 5001), which is empty in a query asking for one
+    Nsid(Vec<u8>),
+    ClientSubnet(ClientSubnet),
+    Cookie(Cookie),
+    /// Padding (RFC 7830) of the given number of zero bytes. The content of received padding
+    /// is ignored.
+    Padding(u16),
+    ExtendedError(ExtendedError),
+    Unknown {
+        code: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl EdnsOption {
+    pub fn from_buffer(buffer: &mut PacketBuffer) -> Result<EdnsOption, String> {
+        let code = buffer.read_u16()?;
+        let len = buffer.read_u16()?;
+
+        match code {
+            OPTION_CODE_NSID => Ok(EdnsOption::Nsid(buffer.read_bytes(len as usize)?)),
+            OPTION_CODE_CLIENT_SUBNET => Ok(EdnsOption::ClientSubnet(ClientSubnet::from_buffer(
+                buffer, len,
+            )?)),
+            OPTION_CODE_COOKIE => Ok(EdnsOption::Cookie(Cookie::from_buffer(buffer, len)?)),
+            OPTION_CODE_PADDING => {
+                buffer.read_bytes(len as usize)?;
+                Ok(EdnsOption::Padding(len))
+            }
+            OPTION_CODE_EXTENDED_ERROR => Ok(EdnsOption::ExtendedError(
+                ExtendedError::from_buffer(buffer, len)?,
+            )),
+            _ => {
+                let data = buffer.read_bytes(len as usize)?;
+                Ok(EdnsOption::Unknown { code, data })
+            }
+        }
+    }
+
+    pub fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        match *self {
+            EdnsOption::Nsid(ref nsid) => {
+                buffer.write_u16(OPTION_CODE_NSID)?;
+                buffer.write_u16(nsid.len() as u16)?;
+                buffer.write_bytes(nsid)?;
+            }
+            EdnsOption::ClientSubnet(ref subnet) => {
+                buffer.write_u16(OPTION_CODE_CLIENT_SUBNET)?;
+                buffer.write_u16(4 + subnet.address.len() as u16)?;
+                subnet.write(buffer)?;
+            }
+            EdnsOption::Cookie(ref cookie) => {
+                buffer.write_u16(OPTION_CODE_COOKIE)?;
+                buffer.write_u16((CLIENT_COOKIE_LEN + cookie.server.len()) as u16)?;
+                cookie.write(buffer)?;
+            }
+            EdnsOption::Padding(len) => {
+                buffer.write_u16(OPTION_CODE_PADDING)?;
+                buffer.write_u16(len)?;
+                for _ in 0..len {
+                    buffer.write(0)?;
+                }
+            }
+            EdnsOption::ExtendedError(ref error) => {
+                buffer.write_u16(OPTION_CODE_EXTENDED_ERROR)?;
+                buffer.write_u16(2 + error.extra_text.len() as u16)?;
+                error.write(buffer)?;
+            }
+            EdnsOption::Unknown { code, ref data } => {
+                buffer.write_u16(code)?;
+                buffer.write_u16(data.len() as u16)?;
+                buffer.write_bytes(data)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// EDNS Client Subnet option (RFC 7871), where `address` holds only the bytes covered by
+/// `source_prefix_len`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientSubnet {
+    pub family: u16,
+    pub source_prefix_len: u8,
+    pub scope_prefix_len: u8,
+    pub address: Vec<u8>,
+}
+
+impl ClientSubnet {
+    /// Derive the subnet to forward for a client address, keeping only the leading
+    /// `source_prefix_len` bits of it
+    pub fn from_addr(addr: IpAddr, source_prefix_len: u8) -> Result<ClientSubnet, String> {
+        let (family, octets) = match addr {
+            IpAddr::V4(addr) => (ADDRESS_FAMILY_IPV4, addr.octets().to_vec()),
+            IpAddr::V6(addr) => (ADDRESS_FAMILY_IPV6, addr.octets().to_vec()),
+        };
+        if source_prefix_len as usize > octets.len() * 8 {
+            let err_str = format!(
+                "Invalid client subnet, source prefix longer than address: address={}, source prefix={}",
+                addr, source_prefix_len
+            );
+            return Err(err_str);
+        }
+
+        let mut address = octets[..prefix_bytes(source_prefix_len)].to_vec();
+        let trailing_bits = source_prefix_len % 8;
+        if trailing_bits != 0 {
+            if let Some(last) = address.last_mut() {
+                *last &= 0xFF << (8 - trailing_bits);
+            }
+        }
+
+        Ok(ClientSubnet {
+            family,
+            source_prefix_len,
+            scope_prefix_len: 0,
+            address,
+        })
+    }
+
+    fn from_buffer(buffer: &mut PacketBuffer, len: u16) -> Result<ClientSubnet, String> {
+        if len < 4 {
+            let err_str = format!(
+                "Invalid client subnet, option too short for family and prefixes: len={}",
+                len
+            );
+            return Err(err_str);
+        }
+        let family = buffer.read_u16()?;
+        let source_prefix_len = buffer.read()?;
+        let scope_prefix_len = buffer.read()?;
+        let address_len = len as usize - 4;
+
+        if family != ADDRESS_FAMILY_IPV4 && family != ADDRESS_FAMILY_IPV6 {
+            let err_str = format!(
+                "Invalid client subnet, unsupported address family: family={}",
+                family
+            );
+            return Err(err_str);
+        }
+        if address_len != prefix_bytes(source_prefix_len) {
+            let err_str = format!(
+                "Invalid client subnet, address length doesn't match source prefix: address length={}, source prefix={}",
+                address_len, source_prefix_len
+            );
+            return Err(err_str);
+        }
+
+        Ok(ClientSubnet {
+            family,
+            source_prefix_len,
+            scope_prefix_len,
+            address: buffer.read_bytes(address_len)?,
+        })
+    }
+
+    fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        buffer.write_u16(self.family)?;
+        buffer.write(self.source_prefix_len)?;
+        buffer.write(self.scope_prefix_len)?;
+        buffer.write_bytes(&self.address)?;
+        Ok(())
+    }
+}
+
+/// DNS cookie option (RFC 7873), where `server` is empty until a server cookie has been learnt
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cookie {
+    pub client: [u8; CLIENT_COOKIE_LEN],
+    pub server: Vec<u8>,
+}
+
+impl Cookie {
+    fn from_buffer(buffer: &mut PacketBuffer, len: u16) -> Result<Cookie, String> {
+        let len = len as usize;
+        let server_len = len.saturating_sub(CLIENT_COOKIE_LEN);
+        if len < CLIENT_COOKIE_LEN
+            || (server_len != 0
+                && !(MIN_SERVER_COOKIE_LEN..=MAX_SERVER_COOKIE_LEN).contains(&server_len))
+        {
+            let err_str = format!("Invalid cookie, unexpected option length: len={}", len);
+            return Err(err_str);
+        }
+
+        let mut client = [0; CLIENT_COOKIE_LEN];
+        client.copy_from_slice(&buffer.read_bytes(CLIENT_COOKIE_LEN)?);
+        let server = buffer.read_bytes(server_len)?;
+        Ok(Cookie { client, server })
+    }
+
+    fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        buffer.write_bytes(&self.client)?;
+        buffer.write_bytes(&self.server)?;
+        Ok(())
+    }
+}
+
+/// Extended DNS error option (RFC 8914), giving a reason for a failed or altered answer
+/// alongside the rcode
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtendedError {
+    pub info_code: u16,
+    pub extra_text: String,
+}
+
+impl ExtendedError {
+    pub fn new(info_code: u16, extra_text: String) -> ExtendedError {
+        ExtendedError {
+            info_code,
+            extra_text,
+        }
+    }
+
+    fn from_buffer(buffer: &mut PacketBuffer, len: u16) -> Result<ExtendedError, String> {
+        if len < 2 {
+            let err_str = format!(
+                "Invalid extended error, option too short for info code: len={}",
+                len
+            );
+            return Err(err_str);
+        }
+        let info_code = buffer.read_u16()?;
+        let extra_text = buffer.read_bytes(len as usize - 2)?;
+        Ok(ExtendedError {
+            info_code,
+            extra_text: String::from_utf8_lossy(&extra_text).into_owned(),
+        })
+    }
+
+    fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        buffer.write_u16(self.info_code)?;
+        buffer.write_bytes(self.extra_text.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Add an extended error option to a response's OPT record, adding an OPT record if needed
+pub fn attach_extended_error(response: &mut DnsPacket, error: ExtendedError) {
+    opt_options_mut(response).push(EdnsOption::ExtendedError(error));
+}
+
+/// Add a client subnet option to an outgoing query, replacing any client subnet it already has
+/// and adding an OPT record if needed
+pub fn attach_client_subnet(query: &mut DnsPacket, subnet: ClientSubnet) {
+    let options = opt_options_mut(query);
+    options.retain(|option| !matches!(option, EdnsOption::ClientSubnet(_)));
+    options.push(EdnsOption::ClientSubnet(subnet));
+}
+
+/// Add this server's identifier to a response if its query asked for one with an NSID option
+pub fn answer_nsid(query: &DnsPacket, response: &mut DnsPacket, nsid: &[u8]) {
+    let requested = query.resources.iter().any(|record| match record {
+        DnsRecord::OPT { options, .. } => options
+            .iter()
+            .any(|option| matches!(option, EdnsOption::Nsid(_))),
+        _ => false,
+    });
+    if requested {
+        let options = opt_options_mut(response);
+        options.retain(|option| !matches!(option, EdnsOption::Nsid(_)));
+        options.push(EdnsOption::Nsid(nsid.to_vec()));
+    }
+}
+
+/// Add a padding option to a query so that its uncompressed wire length is a multiple of
+/// `block_size`, replacing any padding it already has
+pub fn pad_query(query: &mut DnsPacket, block_size: usize) -> Result<(), String> {
+    if block_size == 0 {
+        return Err("Invalid padding, block size must be non-zero".to_string());
+    }
+
+    opt_options_mut(query).retain(|option| !matches!(option, EdnsOption::Padding(_)));
+    let mut buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+    query.write(&mut buffer)?;
+    // The option's own code and length count towards the padded length
+    let unpadded_len = buffer.pos() + 4;
+    let padding = (block_size - unpadded_len % block_size) % block_size;
+    opt_options_mut(query).push(EdnsOption::Padding(padding as u16));
+    Ok(())
+}
+
+/// Client cookies used for upstream queries, along with the server cookies each upstream has
+/// handed back for them
+pub struct CookieJar {
+    /// Per-jar secret that client cookies are derived from
+    secret: RandomState,
+    server_cookies: HashMap<SocketAddr, Vec<u8>>,
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        CookieJar::new()
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar {
+            secret: RandomState::new(),
+            server_cookies: HashMap::new(),
+        }
+    }
+
+    /// Client cookie sent to `server`, which is a keyed hash of the server address so that
+    /// upstreams can't use it to track this server across each other (RFC 7873 section 4.1)
+    pub fn client_cookie(&self, server: &SocketAddr) -> [u8; CLIENT_COOKIE_LEN] {
+        self.secret.hash_one(server).to_be_bytes()
+    }
+
+    pub fn server_cookie(&self, server: &SocketAddr) -> Option<&[u8]> {
+        self.server_cookies
+            .get(server)
+            .map(|cookie| cookie.as_slice())
+    }
+
+    /// Add a cookie option to an outgoing query, including the server cookie previously learnt
+    /// from `server` if there is one
+    pub fn attach(&self, query: &mut DnsPacket, server: &SocketAddr) {
+        let cookie = Cookie {
+            client: self.client_cookie(server),
+            server: self.server_cookies.get(server).cloned().unwrap_or_default(),
+        };
+        let options = opt_options_mut(query);
+        options.retain(|option| !matches!(option, EdnsOption::Cookie(_)));
+        options.push(EdnsOption::Cookie(cookie));
+    }
+
+    /// Remember the server cookie in a response from `server`, rejecting the response if it
+    /// echoes a client cookie other than ours
+    pub fn store(&mut self, response: &DnsPacket, server: SocketAddr) -> Result<(), String> {
+        let cookie = response.resources.iter().find_map(|record| match record {
+            DnsRecord::OPT { options, .. } => options.iter().find_map(|option| match option {
+                EdnsOption::Cookie(cookie) => Some(cookie),
+                _ => None,
+            }),
+            _ => None,
+        });
+
+        let Some(cookie) = cookie else {
+            return Ok(());
+        };
+        if cookie.client != self.client_cookie(&server) {
+            let err_str = format!(
+                "Invalid cookie, client cookie in response doesn't match: server={}",
+                server
+            );
+            return Err(err_str);
+        }
+        if !cookie.server.is_empty() {
+            self.server_cookies.insert(server, cookie.server.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Whether the packet's OPT record has the DO bit set, asking for DNSSEC records
+pub fn dnssec_ok(packet: &DnsPacket) -> bool {
+    packet.resources.iter().any(|record| match *record {
+        DnsRecord::OPT { flags, .. } => flags & FLAG_DNSSEC_OK != 0,
+        _ => false,
+    })
+}
+
+/// Fit a response to the DO bit of the query it answers. With DO set, the bit is echoed in the
+/// response's OPT record. Without it, DNSSEC records are stripped unless the query asked for
+/// their type directly.
+pub fn apply_dnssec_ok(query: &DnsPacket, response: &mut DnsPacket) {
+    if dnssec_ok(query) {
+        *opt_record_mut(response).0 |= FLAG_DNSSEC_OK;
+        return;
+    }
+
+    let queried: Vec<QueryType> = query
+        .questions
+        .iter()
+        .map(|question| question.qtype)
+        .collect();
+    for section in [
+        &mut response.answers,
+        &mut response.authorities,
+        &mut response.resources,
+    ] {
+        section.retain(|record| !record.qtype().is_dnssec() || queried.contains(&record.qtype()));
+    }
+    for record in &mut response.resources {
+        if let DnsRecord::OPT { ref mut flags, .. } = *record {
+            *flags &= !FLAG_DNSSEC_OK;
+        }
+    }
+}
+
+/// Remove OPT records from a response whose query didn't use EDNS, as a client that didn't
+/// send OPT may not understand one in the reply
+pub fn downgrade_edns(query: &DnsPacket, response: &mut DnsPacket) {
+    let query_has_opt = query
+        .resources
+        .iter()
+        .any(|record| matches!(record, DnsRecord::OPT { .. }));
+    if !query_has_opt {
+        response
+            .resources
+            .retain(|record| !matches!(record, DnsRecord::OPT { .. }));
+    }
+}
+
+/// Options of the packet's OPT record, adding an OPT record if the packet doesn't have one
+fn opt_options_mut(packet: &mut DnsPacket) -> &mut Vec<EdnsOption> {
+    opt_record_mut(packet).1
+}
+
+/// Flags and options of the packet's OPT record, adding an OPT record if the packet doesn't
+/// have one
+fn opt_record_mut(packet: &mut DnsPacket) -> (&mut u32, &mut Vec<EdnsOption>) {
+    let idx = match packet
+        .resources
+        .iter()
+        .position(|record| matches!(record, DnsRecord::OPT { .. }))
+    {
+        Some(idx) => idx,
+        None => {
+            packet.resources.push(DnsRecord::OPT {
+                packet_len: PACKET_BYTES_LENGTH as u16,
+                flags: 0,
+                options: Vec::new(),
+            });
+            packet.resources.len() - 1
+        }
+    };
+
+    match packet.resources[idx] {
+        DnsRecord::OPT {
+            ref mut flags,
+            ref mut options,
+            ..
+        } => (flags, options),
+        _ => unreachable!(),
+    }
+}
+
+fn prefix_bytes(prefix_len: u8) -> usize {
+    prefix_len.div_ceil(8) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::ResultCode;
+    use crate::packet::{BlockResponse, RandomTxIds};
+    use crate::question::DnsQuestion;
+    use crate::record::CLASS_IN;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn parse_client_subnet_option() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let option_bytes = [
+            0x00, 0x08, // option code
+            0x00, 0x07, // option length
+            0x00, 0x01, // family
+            0x18, // source prefix length
+            0x00, // scope prefix length
+            0xC0, 0x00, 0x02, // address
+        ];
+        buf[..option_bytes.len()].copy_from_slice(&option_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_option = EdnsOption::ClientSubnet(ClientSubnet {
+            family: 1,
+            source_prefix_len: 24,
+            scope_prefix_len: 0,
+            address: vec![192, 0, 2],
+        });
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_option)
+        );
+        assert_eq!(option_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_client_subnet_address_length_mismatches_prefix() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let option_bytes = [
+            0x00, 0x08, // option code
+            0x00, 0x08, // option length
+            0x00, 0x01, // family
+            0x18, // source prefix length
+            0x00, // scope prefix length
+            0xC0, 0x00, 0x02, 0x01, // address
+        ];
+        buf[..option_bytes.len()].copy_from_slice(&option_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str = "Invalid client subnet, address length doesn't match source prefix: address length=4, source prefix=24";
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_client_subnet_too_short() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let option_bytes = [0x00, 0x08, 0x00, 0x02, 0x00, 0x01];
+        buf[..option_bytes.len()].copy_from_slice(&option_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str = "Invalid client subnet, option too short for family and prefixes: len=2";
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn client_subnet_attached_to_query_survives_round_trip() {
+        let mut query =
+            DnsPacket::new_reverse_query("192.0.2.1".parse().unwrap(), &mut RandomTxIds);
+        let client = "198.51.100.77".parse().unwrap();
+        attach_client_subnet(&mut query, ClientSubnet::from_addr(client, 16).unwrap());
+        attach_client_subnet(&mut query, ClientSubnet::from_addr(client, 24).unwrap());
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        query.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        let expected_option = EdnsOption::ClientSubnet(ClientSubnet {
+            family: 1,
+            source_prefix_len: 24,
+            scope_prefix_len: 0,
+            address: vec![198, 51, 100],
+        });
+        assert_eq!(1, res.resources.len());
+        assert_eq!(
+            true,
+            matches!(&res.resources[0], DnsRecord::OPT { options, .. } if options == &vec![expected_option])
+        );
+    }
+
+    #[test]
+    fn client_subnet_derived_from_ipv4_client_address() {
+        let addr = "198.51.100.77".parse().unwrap();
+        let expected_subnet = ClientSubnet {
+            family: 1,
+            source_prefix_len: 20,
+            scope_prefix_len: 0,
+            address: vec![198, 51, 96],
+        };
+        assert_eq!(
+            true,
+            ClientSubnet::from_addr(addr, 20).is_ok_and(|val| val == expected_subnet)
+        );
+    }
+
+    #[test]
+    fn client_subnet_derived_from_ipv6_client_address() {
+        let addr = "2001:db8:abcd:12::1".parse().unwrap();
+        let expected_subnet = ClientSubnet {
+            family: 2,
+            source_prefix_len: 56,
+            scope_prefix_len: 0,
+            address: vec![0x20, 0x01, 0x0d, 0xb8, 0xab, 0xcd, 0x00],
+        };
+        assert_eq!(
+            true,
+            ClientSubnet::from_addr(addr, 56).is_ok_and(|val| val == expected_subnet)
+        );
+    }
+
+    #[test]
+    fn return_error_if_source_prefix_longer_than_address() {
+        let addr = "198.51.100.77".parse().unwrap();
+        let expected_str = "Invalid client subnet, source prefix longer than address: address=198.51.100.77, source prefix=33";
+        assert_eq!(
+            true,
+            ClientSubnet::from_addr(addr, 33).is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn unknown_option_survives_round_trip() {
+        let option = EdnsOption::Unknown {
+            code: 65001,
+            data: vec![1, 2, 3],
+        };
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        option.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer).is_ok_and(|val| val == option)
+        );
+    }
+
+    fn cookie_from_packet(packet: &DnsPacket) -> Option<&Cookie> {
+        packet.resources.iter().find_map(|record| match record {
+            DnsRecord::OPT { options, .. } => options.iter().find_map(|option| match option {
+                EdnsOption::Cookie(cookie) => Some(cookie),
+                _ => None,
+            }),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn cookie_with_server_cookie_survives_round_trip() {
+        let option = EdnsOption::Cookie(Cookie {
+            client: [1, 2, 3, 4, 5, 6, 7, 8],
+            server: vec![9; 16],
+        });
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        option.write(&mut packet_buffer).unwrap();
+        assert_eq!(4 + 8 + 16, packet_buffer.pos());
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer).is_ok_and(|val| val == option)
+        );
+    }
+
+    #[test]
+    fn return_error_if_server_cookie_too_short() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let option_bytes = [
+            0x00, 0x0A, // option code
+            0x00, 0x0C, // option length
+            1, 2, 3, 4, 5, 6, 7, 8, // client cookie
+            9, 9, 9, 9, // server cookie
+        ];
+        buf[..option_bytes.len()].copy_from_slice(&option_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str = "Invalid cookie, unexpected option length: len=12";
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn client_cookie_attached_to_query_without_opt_record() {
+        let jar = CookieJar::new();
+        let server = "192.0.2.53:53".parse().unwrap();
+        let mut query =
+            DnsPacket::new_reverse_query("192.0.2.1".parse().unwrap(), &mut RandomTxIds);
+        jar.attach(&mut query, &server);
+        let expected_cookie = Cookie {
+            client: jar.client_cookie(&server),
+            server: Vec::new(),
+        };
+        assert_eq!(1, query.resources.len());
+        assert_eq!(Some(&expected_cookie), cookie_from_packet(&query));
+    }
+
+    #[test]
+    fn client_cookie_differs_between_servers_and_is_stable_per_server() {
+        let jar = CookieJar::new();
+        let server = "192.0.2.53:53".parse().unwrap();
+        let other_server = "198.51.100.53:53".parse().unwrap();
+        assert_ne!(jar.client_cookie(&server), jar.client_cookie(&other_server));
+        assert_eq!(jar.client_cookie(&server), jar.client_cookie(&server));
+
+        let mut query = DnsPacket::new();
+        jar.attach(&mut query, &server);
+        let mut other_query = DnsPacket::new();
+        jar.attach(&mut other_query, &other_server);
+        assert_ne!(
+            cookie_from_packet(&query).map(|cookie| cookie.client),
+            cookie_from_packet(&other_query).map(|cookie| cookie.client)
+        );
+    }
+
+    #[test]
+    fn server_cookie_from_response_stored_and_reused_for_same_server() {
+        let mut jar = CookieJar::new();
+        let server = "192.0.2.53:53".parse().unwrap();
+        let other_server = "198.51.100.53:53".parse().unwrap();
+        let client = jar.client_cookie(&server);
+        let mut response = DnsPacket::new();
+        response.header.response = true;
+        response.resources.push(DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: vec![EdnsOption::Cookie(Cookie {
+                client,
+                server: vec![0xAA; 8],
+            })],
+        });
+        assert_eq!(true, jar.store(&response, server).is_ok());
+        assert_eq!(Some(&[0xAA; 8][..]), jar.server_cookie(&server));
+        assert_eq!(None, jar.server_cookie(&other_server));
+
+        let mut query = DnsPacket::new();
+        jar.attach(&mut query, &server);
+        assert_eq!(
+            true,
+            cookie_from_packet(&query).is_some_and(|cookie| cookie.server == [0xAA; 8])
+        );
+        let mut other_query = DnsPacket::new();
+        jar.attach(&mut other_query, &other_server);
+        assert_eq!(
+            true,
+            cookie_from_packet(&other_query).is_some_and(|cookie| cookie.server.is_empty())
+        );
+    }
+
+    #[test]
+    fn return_error_if_response_echoes_different_client_cookie() {
+        let mut jar = CookieJar::new();
+        let server = "192.0.2.53:53".parse().unwrap();
+        let other_server = "198.51.100.53:53".parse().unwrap();
+        let mut response = DnsPacket::new();
+        response.resources.push(DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: vec![EdnsOption::Cookie(Cookie {
+                client: jar.client_cookie(&other_server),
+                server: vec![0xAA; 8],
+            })],
+        });
+        let expected_str =
+            "Invalid cookie, client cookie in response doesn't match: server=192.0.2.53:53";
+        assert_eq!(
+            true,
+            jar.store(&response, server)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+        assert_eq!(None, jar.server_cookie(&server));
+    }
+
+    fn signed_response() -> DnsPacket {
+        let mut response = DnsPacket::new();
+        response.header.response = true;
+        response
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::A));
+        response.answers = vec![
+            DnsRecord::A {
+                domain: "example.com".to_string(),
+                addr: Ipv4Addr::new(93, 184, 216, 34),
+                class: CLASS_IN,
+                ttl: 300,
+            },
+            DnsRecord::Unknown {
+                domain: "example.com".to_string(),
+                qtype: QueryType::RRSIG.to_num(),
+                data_len: 4,
+                data: vec![0x00, 0x01, 0x08, 0x02],
+                class: CLASS_IN,
+                ttl: 300,
+            },
+        ];
+        response
+    }
+
+    fn query_with_flags(qtype: QueryType, flags: u32) -> DnsPacket {
+        let mut query = DnsPacket::new();
+        query
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), qtype));
+        query.resources.push(DnsRecord::OPT {
+            packet_len: 1232,
+            flags,
+            options: Vec::new(),
+        });
+        query
+    }
+
+    #[test]
+    fn dnssec_records_kept_and_do_bit_echoed_when_query_sets_do() {
+        let query = query_with_flags(QueryType::A, FLAG_DNSSEC_OK);
+        let mut response = signed_response();
+        apply_dnssec_ok(&query, &mut response);
+        assert_eq!(signed_response().answers, response.answers);
+        assert_eq!(true, dnssec_ok(&response));
+    }
+
+    #[test]
+    fn dnssec_records_stripped_when_query_clears_do() {
+        let query = query_with_flags(QueryType::A, 0);
+        let mut response = signed_response();
+        apply_dnssec_ok(&query, &mut response);
+        assert_eq!(signed_response().answers[..1], response.answers[..]);
+        assert_eq!(false, dnssec_ok(&response));
+    }
+
+    #[test]
+    fn dnssec_records_of_queried_type_kept_without_do() {
+        let query = query_with_flags(QueryType::RRSIG, 0);
+        let mut response = signed_response();
+        apply_dnssec_ok(&query, &mut response);
+        assert_eq!(signed_response().answers, response.answers);
+    }
+
+    #[test]
+    fn do_bit_read_from_opt_flags() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x00, // root
+            0x00, 0x29, // qtype
+            0x04, 0xD0, // udp payload size
+            0x00, 0x00, 0x80, 0x00, // extended rcode, version and flags with DO set
+            0x00, 0x00, // data length
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet = DnsPacket::new();
+        packet
+            .resources
+            .push(DnsRecord::from_buffer(&mut PacketBuffer::new(buf)).unwrap());
+        assert_eq!(true, dnssec_ok(&packet));
+        assert_eq!(false, dnssec_ok(&DnsPacket::new()));
+    }
+
+    #[test]
+    fn opt_stripped_from_response_to_query_without_edns() {
+        let mut query = DnsPacket::new();
+        query
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::A));
+        let mut response = signed_response();
+        let glue = DnsRecord::A {
+            domain: "ns1.example.com".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 53),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        response.resources = vec![
+            glue.clone(),
+            DnsRecord::OPT {
+                packet_len: 1232,
+                flags: 0,
+                options: Vec::new(),
+            },
+        ];
+        downgrade_edns(&query, &mut response);
+        assert_eq!(vec![glue], response.resources);
+    }
+
+    #[test]
+    fn opt_kept_in_response_to_query_with_edns() {
+        let query = query_with_flags(QueryType::A, 0);
+        let mut response = signed_response();
+        response.resources = query.resources.clone();
+        downgrade_edns(&query, &mut response);
+        assert_eq!(query.resources, response.resources);
+    }
+
+    #[test]
+    fn extended_error_survives_round_trip_in_response() {
+        let mut response = signed_response();
+        response.header.rescode = ResultCode::Refused;
+        attach_extended_error(
+            &mut response,
+            ExtendedError::new(INFO_CODE_BLOCKED, "blocklisted".to_string()),
+        );
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        let error = res.resources.iter().find_map(|record| match record {
+            DnsRecord::OPT { options, .. } => options.iter().find_map(|option| match option {
+                EdnsOption::ExtendedError(error) => Some(error.clone()),
+                _ => None,
+            }),
+            _ => None,
+        });
+        let expected_error = ExtendedError::new(INFO_CODE_BLOCKED, "blocklisted".to_string());
+        assert_eq!(Some(expected_error), error);
+    }
+
+    #[test]
+    fn return_error_if_extended_error_too_short() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let option_bytes = [0x00, 0x0F, 0x00, 0x01, 0x00];
+        buf[..option_bytes.len()].copy_from_slice(&option_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str = "Invalid extended error, option too short for info code: len=1";
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn parse_nsid_request_option() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let option_bytes = [0x00, 0x03, 0x00, 0x00];
+        buf[..option_bytes.len()].copy_from_slice(&option_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer)
+                .is_ok_and(|val| val == EdnsOption::Nsid(Vec::new()))
+        );
+        assert_eq!(option_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn configured_nsid_echoed_when_requested() {
+        let mut query = query_with_flags(QueryType::A, 0);
+        if let DnsRecord::OPT {
+            ref mut options, ..
+        } = query.resources[0]
+        {
+            options.push(EdnsOption::Nsid(Vec::new()));
+        }
+        let mut response = signed_response();
+        answer_nsid(&query, &mut response, b"ns1.lon");
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        let expected_opt = DnsRecord::OPT {
+            packet_len: PACKET_BYTES_LENGTH as u16,
+            flags: 0,
+            options: vec![EdnsOption::Nsid(b"ns1.lon".to_vec())],
+        };
+        assert_eq!(vec![expected_opt], res.resources);
+    }
+
+    #[test]
+    fn nsid_not_added_when_not_requested() {
+        let query = query_with_flags(QueryType::A, 0);
+        let mut response = signed_response();
+        answer_nsid(&query, &mut response, b"ns1.lon");
+        assert_eq!(true, response.resources.is_empty());
+    }
+
+    #[test]
+    fn padded_query_reaches_block_size_and_parses_back() {
+        let mut query = query_with_flags(QueryType::A, 0);
+        pad_query(&mut query, QUERY_PADDING_BLOCK_SIZE).unwrap();
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        query.write(&mut packet_buffer).unwrap();
+        assert_eq!(QUERY_PADDING_BLOCK_SIZE, packet_buffer.pos());
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(query.resources, res.resources);
+    }
+
+    #[test]
+    fn existing_padding_replaced_when_padding_again() {
+        let mut query = query_with_flags(QueryType::A, 0);
+        pad_query(&mut query, QUERY_PADDING_BLOCK_SIZE).unwrap();
+        pad_query(&mut query, 64).unwrap();
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        query.write(&mut packet_buffer).unwrap();
+        assert_eq!(64, packet_buffer.pos());
+    }
+
+    #[test]
+    fn padding_content_ignored_when_parsing() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let option_bytes = [0x00, 0x0C, 0x00, 0x03, 0x01, 0x02, 0x03];
+        buf[..option_bytes.len()].copy_from_slice(&option_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(
+            true,
+            EdnsOption::from_buffer(&mut packet_buffer)
+                .is_ok_and(|val| val == EdnsOption::Padding(3))
+        );
+        assert_eq!(option_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn blocked_response_carries_extended_error() {
+        let mut query = DnsPacket::new();
+        query.questions.push(DnsQuestion::new(
+            "ads.example.com".to_string(),
+            QueryType::A,
+        ));
+        query.resources.push(DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: Vec::new(),
+        });
+        let response = query.blocked_response(BlockResponse::NxDomain);
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        let error = res.resources.iter().find_map(|record| match record {
+            DnsRecord::OPT { options, .. } => options.iter().find_map(|option| match option {
+                EdnsOption::ExtendedError(error) => Some(error.clone()),
+                _ => None,
+            }),
+            _ => None,
+        });
+        let expected_error = ExtendedError::new(INFO_CODE_BLOCKED, String::new());
+        assert_eq!(ResultCode::NxDomain, res.header.rescode);
+        assert_eq!(Some(expected_error), error);
+    }
+}
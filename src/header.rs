@@ -0,0 +1,531 @@
+use std::fmt;
+
+use crate::packet::PacketBuffer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResultCode {
+    NoError,
+    FormErr,
+    ServFail,
+    NxDomain,
+    NotImp,
+    Refused,
+    Unknown(u8),
+}
+
+impl ResultCode {
+    pub fn from_num(num: u8) -> ResultCode {
+        match num {
+            0 => ResultCode::NoError,
+            1 => ResultCode::FormErr,
+            2 => ResultCode::ServFail,
+            3 => ResultCode::NxDomain,
+            4 => ResultCode::NotImp,
+            5 => ResultCode::Refused,
+            _ => ResultCode::Unknown(num),
+        }
+    }
+
+    pub fn to_num(&self) -> u8 {
+        match *self {
+            ResultCode::NoError => 0,
+            ResultCode::FormErr => 1,
+            ResultCode::ServFail => 2,
+            ResultCode::NxDomain => 3,
+            ResultCode::NotImp => 4,
+            ResultCode::Refused => 5,
+            ResultCode::Unknown(num) => num,
+        }
+    }
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResultCode::NoError => write!(f, "NOERROR"),
+            ResultCode::FormErr => write!(f, "FORMERR"),
+            ResultCode::ServFail => write!(f, "SERVFAIL"),
+            ResultCode::NxDomain => write!(f, "NXDOMAIN"),
+            ResultCode::NotImp => write!(f, "NOTIMP"),
+            ResultCode::Refused => write!(f, "REFUSED"),
+            // Codes without a variant of their own, named the same way dig names them
+            ResultCode::Unknown(6) => write!(f, "YXDOMAIN"),
+            ResultCode::Unknown(7) => write!(f, "YXRRSET"),
+            ResultCode::Unknown(8) => write!(f, "NXRRSET"),
+            ResultCode::Unknown(9) => write!(f, "NOTAUTH"),
+            ResultCode::Unknown(10) => write!(f, "NOTZONE"),
+            ResultCode::Unknown(num) => write!(f, "RESERVED{}", num),
+        }
+    }
+}
+
+pub(crate) const HEADER_LENGTH: usize = 12;
+/// Opcode of a NOTIFY message, telling a secondary that a zone has changed (RFC 1996)
+pub const OPCODE_NOTIFY: u8 = 4;
+
+const QR_SHIFT: u16 = 15;
+const OPCODE_SHIFT: u16 = 11;
+const AA_SHIFT: u16 = 10;
+const TC_SHIFT: u16 = 9;
+const RD_SHIFT: u16 = 8;
+const RA_SHIFT: u16 = 7;
+const Z_SHIFT: u16 = 6;
+const AD_SHIFT: u16 = 5;
+const CD_SHIFT: u16 = 4;
+const OPCODE_MASK: u16 = 0x0F;
+const RCODE_MASK: u16 = 0x0F;
+
+/// The 16 bits of header flags between the id and the section counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HeaderFlags(u16);
+
+impl HeaderFlags {
+    pub fn from_u16(val: u16) -> HeaderFlags {
+        HeaderFlags(val)
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        self.0
+    }
+
+    pub fn qr(&self) -> bool {
+        self.bit(QR_SHIFT)
+    }
+
+    pub fn set_qr(&mut self, val: bool) {
+        self.set_bit(QR_SHIFT, val)
+    }
+
+    pub fn opcode(&self) -> u8 {
+        ((self.0 >> OPCODE_SHIFT) & OPCODE_MASK) as u8
+    }
+
+    pub fn set_opcode(&mut self, val: u8) {
+        self.0 = (self.0 & !(OPCODE_MASK << OPCODE_SHIFT))
+            | ((val as u16 & OPCODE_MASK) << OPCODE_SHIFT);
+    }
+
+    pub fn aa(&self) -> bool {
+        self.bit(AA_SHIFT)
+    }
+
+    pub fn set_aa(&mut self, val: bool) {
+        self.set_bit(AA_SHIFT, val)
+    }
+
+    pub fn tc(&self) -> bool {
+        self.bit(TC_SHIFT)
+    }
+
+    pub fn set_tc(&mut self, val: bool) {
+        self.set_bit(TC_SHIFT, val)
+    }
+
+    pub fn rd(&self) -> bool {
+        self.bit(RD_SHIFT)
+    }
+
+    pub fn set_rd(&mut self, val: bool) {
+        self.set_bit(RD_SHIFT, val)
+    }
+
+    pub fn ra(&self) -> bool {
+        self.bit(RA_SHIFT)
+    }
+
+    pub fn set_ra(&mut self, val: bool) {
+        self.set_bit(RA_SHIFT, val)
+    }
+
+    pub fn z(&self) -> bool {
+        self.bit(Z_SHIFT)
+    }
+
+    pub fn set_z(&mut self, val: bool) {
+        self.set_bit(Z_SHIFT, val)
+    }
+
+    pub fn ad(&self) -> bool {
+        self.bit(AD_SHIFT)
+    }
+
+    pub fn set_ad(&mut self, val: bool) {
+        self.set_bit(AD_SHIFT, val)
+    }
+
+    pub fn cd(&self) -> bool {
+        self.bit(CD_SHIFT)
+    }
+
+    pub fn set_cd(&mut self, val: bool) {
+        self.set_bit(CD_SHIFT, val)
+    }
+
+    pub fn rcode(&self) -> u8 {
+        (self.0 & RCODE_MASK) as u8
+    }
+
+    pub fn set_rcode(&mut self, val: u8) {
+        self.0 = (self.0 & !RCODE_MASK) | (val as u16 & RCODE_MASK);
+    }
+
+    fn bit(&self, shift: u16) -> bool {
+        (self.0 >> shift) & 1 == 1
+    }
+
+    fn set_bit(&mut self, shift: u16, val: bool) {
+        if val {
+            self.0 |= 1 << shift;
+        } else {
+            self.0 &= !(1 << shift);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DnsHeader {
+    pub id: u16,
+
+    pub response: bool,
+    pub opcode: u8,
+    pub authoritative_answer: bool,
+    pub truncated_message: bool,
+    pub recursion_desired: bool,
+
+    pub recursion_available: bool,
+    pub z: bool,
+    pub authed_data: bool,
+    pub checking_disabled: bool,
+    pub rescode: ResultCode,
+
+    pub questions: u16,
+    pub answers: u16,
+    pub authoritative_entries: u16,
+    pub resource_entries: u16,
+}
+
+impl Default for DnsHeader {
+    fn default() -> Self {
+        DnsHeader::new()
+    }
+}
+
+impl DnsHeader {
+    pub fn new() -> DnsHeader {
+        DnsHeader {
+            id: 0,
+            response: false,
+            opcode: 0,
+            authoritative_answer: false,
+            truncated_message: false,
+            recursion_desired: false,
+            recursion_available: false,
+            z: false,
+            authed_data: false,
+            checking_disabled: false,
+            rescode: ResultCode::NoError,
+            questions: 0,
+            answers: 0,
+            authoritative_entries: 0,
+            resource_entries: 0,
+        }
+    }
+
+    pub fn from_buffer(buffer: &mut PacketBuffer) -> Result<DnsHeader, String> {
+        let id = buffer.read_u16()?;
+        let flags = HeaderFlags::from_u16(buffer.read_u16()?);
+        let mut counts = [0; 4];
+        for count in counts.iter_mut() {
+            *count = buffer.read_u16()?;
+        }
+        Ok(DnsHeader::from_parts(id, flags, counts))
+    }
+
+    /// Parse only the header at the start of `data`, without copying the message into a
+    /// `PacketBuffer` or looking at anything after the first 12 bytes
+    pub fn peek(data: &[u8]) -> Result<DnsHeader, String> {
+        if data.len() < HEADER_LENGTH {
+            let err_str = format!(
+                "Invalid header, message shorter than header: header length={}, len={}",
+                HEADER_LENGTH,
+                data.len()
+            );
+            return Err(err_str);
+        }
+
+        let read_u16 = |pos: usize| ((data[pos] as u16) << 8) | data[pos + 1] as u16;
+        let counts = [read_u16(4), read_u16(6), read_u16(8), read_u16(10)];
+        Ok(DnsHeader::from_parts(
+            read_u16(0),
+            HeaderFlags::from_u16(read_u16(2)),
+            counts,
+        ))
+    }
+
+    /// Header from its id, flags and the question, answer, authority and additional counts
+    fn from_parts(id: u16, flags: HeaderFlags, counts: [u16; 4]) -> DnsHeader {
+        DnsHeader {
+            id,
+            response: flags.qr(),
+            opcode: flags.opcode(),
+            authoritative_answer: flags.aa(),
+            truncated_message: flags.tc(),
+            recursion_desired: flags.rd(),
+            recursion_available: flags.ra(),
+            z: flags.z(),
+            authed_data: flags.ad(),
+            checking_disabled: flags.cd(),
+            rescode: ResultCode::from_num(flags.rcode()),
+            questions: counts[0],
+            answers: counts[1],
+            authoritative_entries: counts[2],
+            resource_entries: counts[3],
+        }
+    }
+
+    pub fn flags(&self) -> HeaderFlags {
+        let mut flags = HeaderFlags::default();
+        flags.set_qr(self.response);
+        flags.set_opcode(self.opcode);
+        flags.set_aa(self.authoritative_answer);
+        flags.set_tc(self.truncated_message);
+        flags.set_rd(self.recursion_desired);
+        flags.set_ra(self.recursion_available);
+        flags.set_z(self.z);
+        flags.set_ad(self.authed_data);
+        flags.set_cd(self.checking_disabled);
+        flags.set_rcode(self.rescode.to_num());
+        flags
+    }
+
+    pub fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        buffer.write_u16(self.id)?;
+        buffer.write_u16(self.flags().to_u16())?;
+        buffer.write_u16(self.questions)?;
+        buffer.write_u16(self.answers)?;
+        buffer.write_u16(self.authoritative_entries)?;
+        buffer.write_u16(self.resource_entries)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::PACKET_BYTES_LENGTH;
+
+    fn round_trip(header: &DnsHeader) -> DnsHeader {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        header.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        DnsHeader::from_buffer(&mut packet_buffer).unwrap()
+    }
+
+    #[test]
+    fn parse_header_fields_from_buffer() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let header_bytes = [
+            0x86, 0x2a, // id
+            0x81, 0x80, // QR, RD, RA set
+            0x00, 0x01, // questions
+            0x00, 0x02, // answers
+            0x00, 0x03, // authoritative entries
+            0x00, 0x04, // resource entries
+        ];
+        buf[..header_bytes.len()].copy_from_slice(&header_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let header = DnsHeader::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(0x862a, header.id);
+        assert_eq!(true, header.response);
+        assert_eq!(true, header.recursion_desired);
+        assert_eq!(true, header.recursion_available);
+        assert_eq!(false, header.authed_data);
+        assert_eq!(false, header.checking_disabled);
+        assert_eq!(ResultCode::NoError, header.rescode);
+        assert_eq!(1, header.questions);
+        assert_eq!(2, header.answers);
+        assert_eq!(3, header.authoritative_entries);
+        assert_eq!(4, header.resource_entries);
+        assert_eq!(12, packet_buffer.pos());
+    }
+
+    #[test]
+    fn authed_data_bit_survives_round_trip() {
+        let mut header = DnsHeader::new();
+        header.authed_data = true;
+        let res = round_trip(&header);
+        assert_eq!(true, res.authed_data);
+        assert_eq!(false, res.checking_disabled);
+        assert_eq!(header, res);
+    }
+
+    #[test]
+    fn checking_disabled_bit_survives_round_trip() {
+        let mut header = DnsHeader::new();
+        header.checking_disabled = true;
+        let res = round_trip(&header);
+        assert_eq!(true, res.checking_disabled);
+        assert_eq!(false, res.authed_data);
+        assert_eq!(header, res);
+    }
+
+    #[test]
+    fn authed_data_and_checking_disabled_written_to_correct_bits() {
+        let mut header = DnsHeader::new();
+        header.authed_data = true;
+        header.checking_disabled = true;
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        header.write(&mut packet_buffer).unwrap();
+        assert_eq!(
+            true,
+            packet_buffer.get_range(3, 1).is_ok_and(|val| val == [0x30])
+        );
+    }
+
+    #[test]
+    fn all_header_fields_survive_round_trip() {
+        let header = DnsHeader {
+            id: 0xbeef,
+            response: true,
+            opcode: 2,
+            authoritative_answer: true,
+            truncated_message: true,
+            recursion_desired: true,
+            recursion_available: true,
+            z: false,
+            authed_data: true,
+            checking_disabled: true,
+            rescode: ResultCode::NxDomain,
+            questions: 1,
+            answers: 2,
+            authoritative_entries: 3,
+            resource_entries: 4,
+        };
+        assert_eq!(header, round_trip(&header));
+    }
+
+    #[test]
+    fn result_codes_displayed_as_dig_status() {
+        assert_eq!("NOERROR", ResultCode::NoError.to_string());
+        assert_eq!("FORMERR", ResultCode::FormErr.to_string());
+        assert_eq!("SERVFAIL", ResultCode::ServFail.to_string());
+        assert_eq!("NXDOMAIN", ResultCode::NxDomain.to_string());
+        assert_eq!("NOTIMP", ResultCode::NotImp.to_string());
+        assert_eq!("REFUSED", ResultCode::Refused.to_string());
+        assert_eq!("YXDOMAIN", ResultCode::Unknown(6).to_string());
+        assert_eq!("YXRRSET", ResultCode::Unknown(7).to_string());
+        assert_eq!("NXRRSET", ResultCode::Unknown(8).to_string());
+        assert_eq!("NOTAUTH", ResultCode::Unknown(9).to_string());
+        assert_eq!("NOTZONE", ResultCode::Unknown(10).to_string());
+    }
+
+    #[test]
+    fn unassigned_result_code_displayed_as_reserved() {
+        assert_eq!("RESERVED11", ResultCode::Unknown(11).to_string());
+    }
+
+    #[test]
+    fn each_bool_flag_sets_exactly_its_own_bit() {
+        type Setter = fn(&mut HeaderFlags, bool);
+        type Getter = fn(&HeaderFlags) -> bool;
+        let setters: [(Setter, Getter, u16); 8] = [
+            (HeaderFlags::set_qr, HeaderFlags::qr, 0x8000),
+            (HeaderFlags::set_aa, HeaderFlags::aa, 0x0400),
+            (HeaderFlags::set_tc, HeaderFlags::tc, 0x0200),
+            (HeaderFlags::set_rd, HeaderFlags::rd, 0x0100),
+            (HeaderFlags::set_ra, HeaderFlags::ra, 0x0080),
+            (HeaderFlags::set_z, HeaderFlags::z, 0x0040),
+            (HeaderFlags::set_ad, HeaderFlags::ad, 0x0020),
+            (HeaderFlags::set_cd, HeaderFlags::cd, 0x0010),
+        ];
+        for (set, get, expected_bits) in setters {
+            let mut flags = HeaderFlags::default();
+            set(&mut flags, true);
+            assert_eq!(expected_bits, flags.to_u16());
+            assert_eq!(true, get(&flags));
+
+            let mut flags = HeaderFlags::from_u16(0xFFFF);
+            set(&mut flags, false);
+            assert_eq!(!expected_bits, flags.to_u16());
+            assert_eq!(false, get(&flags));
+        }
+    }
+
+    #[test]
+    fn opcode_sets_exactly_its_own_bits() {
+        let mut flags = HeaderFlags::default();
+        flags.set_opcode(0x0F);
+        assert_eq!(0x7800, flags.to_u16());
+        assert_eq!(0x0F, flags.opcode());
+
+        let mut flags = HeaderFlags::from_u16(0xFFFF);
+        flags.set_opcode(0);
+        assert_eq!(0x87FF, flags.to_u16());
+        assert_eq!(0, flags.opcode());
+    }
+
+    #[test]
+    fn rcode_sets_exactly_its_own_bits() {
+        let mut flags = HeaderFlags::default();
+        flags.set_rcode(0x0F);
+        assert_eq!(0x000F, flags.to_u16());
+        assert_eq!(0x0F, flags.rcode());
+
+        let mut flags = HeaderFlags::from_u16(0xFFFF);
+        flags.set_rcode(0);
+        assert_eq!(0xFFF0, flags.to_u16());
+        assert_eq!(0, flags.rcode());
+    }
+
+    #[test]
+    fn out_of_range_opcode_and_rcode_masked_to_four_bits() {
+        let mut flags = HeaderFlags::default();
+        flags.set_opcode(0x12);
+        flags.set_rcode(0x13);
+        assert_eq!(0x1003, flags.to_u16());
+    }
+
+    #[test]
+    fn header_flags_match_header_fields() {
+        let mut header = DnsHeader::new();
+        header.response = true;
+        header.recursion_desired = true;
+        header.rescode = ResultCode::ServFail;
+        assert_eq!(0x8102, header.flags().to_u16());
+    }
+
+    #[test]
+    fn peek_header_from_start_of_larger_packet() {
+        let packet_bytes = [
+            0x86, 0x2a, // id
+            0x81, 0x83, // QR, RD, RA set with NXDOMAIN
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // counts
+            0x06, b'g', b'o', b'o', b'g', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // google.com
+            0x00, 0x01, // qtype
+            0x00, 0x01, // class
+        ];
+        let header = DnsHeader::peek(&packet_bytes).unwrap();
+        assert_eq!(0x862a, header.id);
+        assert_eq!(true, header.response);
+        assert_eq!(0, header.opcode);
+        assert_eq!(ResultCode::NxDomain, header.rescode);
+        assert_eq!(1, header.questions);
+        assert_eq!(0, header.answers);
+
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        buf[..packet_bytes.len()].copy_from_slice(&packet_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(DnsHeader::from_buffer(&mut packet_buffer).unwrap(), header);
+    }
+
+    #[test]
+    fn return_error_if_peeking_message_shorter_than_header() {
+        let expected_str = "Invalid header, message shorter than header: header length=12, len=4";
+        assert_eq!(
+            true,
+            DnsHeader::peek(&[0x86, 0x2a, 0x81, 0x80])
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+}
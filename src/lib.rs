@@ -1 +1,15 @@
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+
+pub mod axfr;
+pub mod edns;
+pub mod header;
 pub mod packet;
+pub mod query_type;
+pub mod question;
+pub mod rebind;
+pub mod record;
+pub mod routes;
+pub mod svcb;
+#[cfg(test)]
+mod test_util;
+pub mod tsig;
@@ -1,24 +1,241 @@
-const PACKET_BYTES_LENGTH: usize = 512;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+use crate::edns::{attach_extended_error, ExtendedError, INFO_CODE_BLOCKED};
+use crate::header::{DnsHeader, ResultCode, HEADER_LENGTH, OPCODE_NOTIFY};
+use crate::query_type::QueryType;
+use crate::question::DnsQuestion;
+use crate::record::{absolute_name, class_mnemonic, in_zone, DnsRecord};
+
+pub(crate) const PACKET_BYTES_LENGTH: usize = 512;
+const DEFAULT_MAX_JUMPS: usize = 5;
+const MAX_LABEL_LENGTH: usize = 0x3F;
+/// Longest a name can be on the wire, counting every length byte and the terminating root
+const MAX_NAME_LENGTH: usize = 255;
+/// Largest offset a 14 bit compression pointer can hold
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+/// Smallest possible encodings: a root name followed by the fixed-size fields
+const MIN_QUESTION_LENGTH: usize = 1 + 4;
+const MIN_RECORD_LENGTH: usize = 1 + 10;
+/// TTL of sinkhole answers to blocked queries, kept short so that unblocking takes effect soon
+const SINKHOLE_TTL: u32 = 60;
+/// TTL of the answers this server gives itself for `localhost` and its reverse names
+const LOCALHOST_TTL: u32 = 86400;
+/// TTL of answers to health probes, which are never cached so each probe reaches this server
+const HEALTH_TTL: u32 = 0;
+
+/// How label bytes that aren't printable ASCII are turned into the characters of a name.
+/// Names are always written as if in the `Escape` form, so names parsed with it are written
+/// back byte for byte.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LabelPolicy {
+    /// Reject names containing any byte above 0x7F
+    Reject,
+    /// Write bytes outside printable ASCII in the `\DDD` decimal form of RFC 4343, and `.` and
+    /// `\` within a label with a preceding backslash, the same way dig displays them
+    #[default]
+    Escape,
+    /// Decode labels as UTF-8, replacing invalid sequences with U+FFFD
+    Lossy,
+}
+
+/// Controls how strictly names in a packet are parsed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParseOptions {
+    /// Reject compression pointers that don't point backwards to earlier in the packet, rather
+    /// than following them
+    pub strict_compression: bool,
+    pub max_jumps: usize,
+    pub label_policy: LabelPolicy,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            strict_compression: false,
+            max_jumps: DEFAULT_MAX_JUMPS,
+            label_policy: LabelPolicy::default(),
+        }
+    }
+}
+
+/// Source of transaction ids for outgoing queries, so that tests can make the ids predictable
+pub trait TxIdSource {
+    fn next(&mut self) -> u16;
+}
+
+/// Transaction ids drawn at random, as queries should use to resist spoofed responses
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomTxIds;
+
+impl TxIdSource for RandomTxIds {
+    fn next(&mut self) -> u16 {
+        RandomState::new().build_hasher().finish() as u16
+    }
+}
+
+/// Transaction ids counting up from a starting id, wrapping on overflow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequentialTxIds {
+    next: u16,
+}
+
+impl SequentialTxIds {
+    pub fn starting_at(id: u16) -> SequentialTxIds {
+        SequentialTxIds { next: id }
+    }
+}
+
+impl TxIdSource for SequentialTxIds {
+    fn next(&mut self) -> u16 {
+        let id = self.next;
+        self.next = self.next.wrapping_add(1);
+        id
+    }
+}
+
+/// How to answer a query for a blocked name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockResponse {
+    NxDomain,
+    /// NOERROR with no answers
+    NoData,
+    /// Answer with this address for questions of its family, and with NODATA for others
+    Sinkhole(IpAddr),
+    Refused,
+}
+
+/// Names that occur more than once in a packet being written, along with where each was first
+/// written so that later occurrences can point back to it
+struct NameCompression {
+    recurring: HashSet<String>,
+    offsets: HashMap<String, usize>,
+}
 
 pub struct PacketBuffer {
-    buf: [u8; PACKET_BYTES_LENGTH],
+    /// Storage for the message, which writes can't go past. This is 512 bytes unless the buffer
+    /// was made by `from_message`.
+    buf: Vec<u8>,
     pos: usize,
+    /// Number of bytes at the start of `buf` holding message data, which reads can't go past
+    len: usize,
+    /// Set when a read fails for going past `len`, so that running out of bytes can be told
+    /// apart from other parse errors
+    overran: Cell<bool>,
+    options: ParseOptions,
+    compression: Option<NameCompression>,
 }
 
 impl PacketBuffer {
     pub fn new(buf: [u8; PACKET_BYTES_LENGTH]) -> PacketBuffer {
-        PacketBuffer { buf, pos: 0 }
+        PacketBuffer {
+            buf: buf.to_vec(),
+            pos: 0,
+            len: PACKET_BYTES_LENGTH,
+            overran: Cell::new(false),
+            options: ParseOptions::default(),
+            compression: None,
+        }
+    }
+
+    pub fn from_reader<R: Read>(reader: &mut R, len: usize) -> Result<PacketBuffer, String> {
+        if len > PACKET_BYTES_LENGTH {
+            let err_str = format!(
+                "Invalid length, reading more bytes than buffer can hold: buffer length={}, len={}",
+                PACKET_BYTES_LENGTH, len
+            );
+            return Err(err_str);
+        }
+
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        if let Err(e) = reader.read_exact(&mut buf[..len]) {
+            let err_str = format!("Failed to read {} bytes from reader: {}", len, e);
+            return Err(err_str);
+        }
+        let mut buffer = PacketBuffer::new(buf);
+        buffer.len = len;
+        Ok(buffer)
+    }
+
+    /// Empty buffer that up to `capacity` bytes can be written to, for messages that don't fit
+    /// in the 512 bytes of a UDP datagram such as those sent over TCP
+    pub fn with_capacity(capacity: usize) -> PacketBuffer {
+        PacketBuffer {
+            buf: vec![0; capacity],
+            pos: 0,
+            len: 0,
+            overran: Cell::new(false),
+            options: ParseOptions::default(),
+            compression: None,
+        }
+    }
+
+    /// Copy a whole message into a new buffer sized to fit it, as messages over TCP can be up to
+    /// 65535 bytes rather than the 512 bytes of a UDP datagram. Writes can't go past the message.
+    pub fn from_message(message: &[u8]) -> PacketBuffer {
+        PacketBuffer {
+            buf: message.to_vec(),
+            pos: 0,
+            len: message.len(),
+            overran: Cell::new(false),
+            options: ParseOptions::default(),
+            compression: None,
+        }
+    }
+
+    /// Receive a datagram from `socket` into a new buffer, returning it along with the sender's
+    /// address. The buffer's length is that of the datagram, so reads past its end fail. Any
+    /// part of a datagram longer than the buffer is discarded.
+    pub fn recv_from(socket: &UdpSocket) -> Result<(PacketBuffer, SocketAddr), String> {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        match socket.recv_from(&mut buf) {
+            Ok((len, src)) => {
+                let mut buffer = PacketBuffer::new(buf);
+                buffer.len = len;
+                Ok((buffer, src))
+            }
+            Err(e) => Err(format!("Failed to receive datagram: {}", e)),
+        }
     }
 
     pub fn pos(&self) -> usize {
         self.pos
     }
 
+    /// Number of bytes of message data in the buffer. This is the whole buffer unless it was
+    /// filled by `from_reader` or `recv_from`, and grows as bytes are written past it.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn parse_options(&self) -> ParseOptions {
+        self.options
+    }
+
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.options = options;
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.len.saturating_sub(self.pos)
+    }
+
     pub fn step(&mut self, step: usize) -> Result<(), String> {
-        if self.pos + step >= PACKET_BYTES_LENGTH {
+        if self.pos + step >= self.buf.len() {
             let err_str = format!(
                 "Invalid step, stepping past buffer boundary: buffer length={}, pos={}, step={}",
-                PACKET_BYTES_LENGTH, self.pos, step
+                self.buf.len(),
+                self.pos,
+                step
             );
             return Err(err_str);
         }
@@ -28,10 +245,11 @@ impl PacketBuffer {
     }
 
     pub fn seek(&mut self, pos: usize) -> Result<(), String> {
-        if pos >= PACKET_BYTES_LENGTH {
+        if pos >= self.buf.len() {
             let err_str = format!(
                 "Invalid seek, seeking past buffer boundary: buffer length={}, seek={}",
-                PACKET_BYTES_LENGTH, pos
+                self.buf.len(),
+                pos
             );
             return Err(err_str);
         }
@@ -41,10 +259,11 @@ impl PacketBuffer {
     }
 
     pub fn read(&mut self) -> Result<u8, String> {
-        if self.pos >= PACKET_BYTES_LENGTH {
+        if self.pos >= self.len {
+            self.overran.set(true);
             let err_str = format!(
                 "Invalid read, reading past buffer boundary: buffer length={}, pos={}",
-                PACKET_BYTES_LENGTH, self.pos
+                self.len, self.pos
             );
             return Err(err_str);
         }
@@ -58,18 +277,144 @@ impl PacketBuffer {
     }
 
     pub fn read_u32(&mut self) -> Result<u32, String> {
-        let res = ((self.read()? as u32) << 8 * 3)
-            | ((self.read()? as u32) << 8 * 2)
+        let res = ((self.read()? as u32) << (8 * 3))
+            | ((self.read()? as u32) << (8 * 2))
             | ((self.read()? as u32) << 8)
             | (self.read()? as u32);
         Ok(res)
     }
 
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, String> {
+        if self.pos + len > self.len {
+            self.overran.set(true);
+            let err_str = format!(
+                "Invalid read, reading bytes past buffer boundary: buffer length={}, pos={}, len={}",
+                self.len, self.pos, len
+            );
+            return Err(err_str);
+        }
+        let res = self.buf[self.pos..self.pos + len].to_vec();
+        self.pos += len;
+        Ok(res)
+    }
+
+    pub fn read_qname(&mut self) -> Result<String, String> {
+        let mut qname = String::new();
+        self.read_qname_into(&mut qname)?;
+        Ok(qname)
+    }
+
+    /// Same as `read_qname`, but clears and refills `qname` so that its allocation can be
+    /// reused across names
+    pub fn read_qname_into(&mut self, qname: &mut String) -> Result<(), String> {
+        qname.clear();
+        let mut pos = self.pos;
+        let mut jumped = false;
+        let mut jumps_performed = 0;
+        let mut delim = "";
+        // Counts labels reached through pointers as well, so that a chain of pointers can't
+        // decode to a longer name than could be written uncompressed
+        let mut name_len = 1;
+
+        loop {
+            if jumps_performed > self.options.max_jumps {
+                let err_str = format!(
+                    "Invalid qname, exceeded maximum number of jumps: max jumps={}",
+                    self.options.max_jumps
+                );
+                return Err(err_str);
+            }
+
+            let len = self.get_range(pos, 1)?[0];
+
+            // Two most significant bits set means the length byte is the first half of a
+            // compression pointer to a name elsewhere in the packet
+            if (len & 0xC0) == 0xC0 {
+                let pointer = self.get_range(pos, 2)?;
+                let offset = ((((pointer[0] & 0x3F) as u16) << 8) | pointer[1] as u16) as usize;
+                // No name can start inside the fixed-size header that begins every packet
+                if offset < HEADER_LENGTH {
+                    let err_str = format!(
+                        "Invalid qname, compression pointer points into the header: pos={}, offset={}",
+                        pos, offset
+                    );
+                    return Err(err_str);
+                }
+                if self.options.strict_compression && offset >= pos {
+                    let err_str = format!(
+                        "Invalid qname, compression pointer doesn't point backwards: pos={}, offset={}",
+                        pos, offset
+                    );
+                    return Err(err_str);
+                }
+                // Only the first jump moves the buffer position, and it moves it past the
+                // pointer rather than to the end of the name being jumped to
+                if !jumped {
+                    self.pos = pos + 2;
+                }
+                pos = offset;
+                jumped = true;
+                jumps_performed += 1;
+                continue;
+            }
+
+            // Top two bits of 01 mark the obsolete extended label types (such as RFC 2673
+            // bit-string labels) and 10 is reserved, neither can be read as a normal label
+            if (len & 0xC0) != 0 {
+                let err_str = format!(
+                    "Invalid qname, unsupported label type: length byte={:#04x}, pos={}",
+                    len, pos
+                );
+                return Err(err_str);
+            }
+
+            pos += 1;
+            if len == 0 {
+                break;
+            }
+
+            name_len += 1 + len as usize;
+            if name_len > MAX_NAME_LENGTH {
+                let err_str = format!(
+                    "Invalid qname, name exceeds maximum length: max length={}, pos={}",
+                    MAX_NAME_LENGTH,
+                    pos - 1
+                );
+                return Err(err_str);
+            }
+
+            qname.push_str(delim);
+            let label = self.get_range(pos, len as usize)?;
+            match self.options.label_policy {
+                LabelPolicy::Reject => {
+                    if let Some(byte) = label.iter().find(|byte| !byte.is_ascii()) {
+                        let err_str = format!(
+                            "Invalid qname, label contains non-ASCII byte: byte={:#04x}, pos={}",
+                            byte, pos
+                        );
+                        return Err(err_str);
+                    }
+                    qname.push_str(&String::from_utf8_lossy(label));
+                }
+                LabelPolicy::Escape => push_escaped_label(qname, label),
+                LabelPolicy::Lossy => qname.push_str(&String::from_utf8_lossy(label)),
+            }
+            delim = ".";
+            pos += len as usize;
+        }
+
+        if !jumped {
+            self.pos = pos;
+        }
+        Ok(())
+    }
+
     pub fn get(&self) -> Result<u8, String> {
-        if self.pos >= PACKET_BYTES_LENGTH {
+        if self.pos >= self.len {
+            self.overran.set(true);
             let err_str = format!(
                 "Invalid get, getting value past buffer boundary: buffer length={}, pos={}",
-                PACKET_BYTES_LENGTH, self.pos
+                self.len, self.pos
             );
             return Err(err_str);
         }
@@ -78,186 +423,2995 @@ impl PacketBuffer {
     }
 
     pub fn get_range(&self, start: usize, len: usize) -> Result<&[u8], String> {
-        if start + len >= PACKET_BYTES_LENGTH {
+        if start + len > self.len {
+            self.overran.set(true);
             let err_str = format!(
                 "Invalid range, getting range past buffer boundary: buffer length={}, start={}, len={}",
-                PACKET_BYTES_LENGTH, start, len
+                self.len, start, len
             );
             return Err(err_str);
         }
 
         Ok(&self.buf[start..start + len])
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    pub fn write(&mut self, val: u8) -> Result<(), String> {
+        if self.pos >= self.buf.len() {
+            let err_str = format!(
+                "Invalid write, writing past buffer boundary: buffer length={}, pos={}",
+                self.buf.len(),
+                self.pos
+            );
+            return Err(err_str);
+        }
+        self.buf[self.pos] = val;
+        self.pos += 1;
+        self.len = self.len.max(self.pos);
+        Ok(())
+    }
 
-    #[test]
-    fn current_position_within_new_packet_buffer_is_zero() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let packet_buffer = PacketBuffer::new(buf);
-        assert_eq!(0, packet_buffer.pos());
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<(), String> {
+        if self.pos + data.len() > self.buf.len() {
+            let err_str = format!(
+                "Invalid write, writing bytes past buffer boundary: buffer length={}, pos={}, len={}",
+                self.buf.len(),
+                self.pos,
+                data.len()
+            );
+            return Err(err_str);
+        }
+        self.buf[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+        self.len = self.len.max(self.pos);
+        Ok(())
     }
 
-    #[test]
-    fn step_position_forward_in_buffer() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let mut packet_buffer = PacketBuffer::new(buf);
-        let step = 5;
-        let res = packet_buffer.step(step);
-        assert_eq!(true, res.is_ok());
-        assert_eq!(step, packet_buffer.pos());
+    pub fn write_u16(&mut self, val: u16) -> Result<(), String> {
+        self.write((val >> 8) as u8)?;
+        self.write((val & 0xFF) as u8)?;
+        Ok(())
     }
 
-    #[test]
-    fn return_error_if_stepping_past_end_of_buffer() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let mut packet_buffer = PacketBuffer::new(buf);
-        let invalid_step = PACKET_BYTES_LENGTH + 1;
-        let res = packet_buffer.step(invalid_step);
-        let expected_err_str =
-            "Invalid step, stepping past buffer boundary: buffer length=512, pos=0, step=513";
-        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_err_str));
+    pub fn write_u32(&mut self, val: u32) -> Result<(), String> {
+        self.write((val >> (8 * 3)) as u8)?;
+        self.write(((val >> (8 * 2)) & 0xFF) as u8)?;
+        self.write(((val >> 8) & 0xFF) as u8)?;
+        self.write((val & 0xFF) as u8)?;
+        Ok(())
     }
 
-    #[test]
-    fn seek_to_position_within_buffer() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let mut packet_buffer = PacketBuffer::new(buf);
-        let new_pos = 51;
-        let res = packet_buffer.seek(new_pos);
-        assert_eq!(true, res.is_ok());
-        assert_eq!(new_pos, packet_buffer.pos());
+    pub fn write_qname(&mut self, qname: &str) -> Result<(), String> {
+        for label in name_labels(qname) {
+            self.write_label(label)?;
+        }
+
+        self.write(0)?;
+        Ok(())
     }
 
-    #[test]
-    fn return_error_if_seeking_past_end_of_buffer() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let mut packet_buffer = PacketBuffer::new(buf);
-        let invalid_pos = 600;
-        let res = packet_buffer.seek(invalid_pos);
-        let expected_str =
-            "Invalid seek, seeking past buffer boundary: buffer length=512, seek=600";
-        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    /// Write a name that may be compressed, which is the case for owner names and names within
+    /// the record data of the types in RFC 1035. Unless the buffer is being written by
+    /// `DnsPacket::write_compressed` this is the same as `write_qname`.
+    pub fn write_compressible_qname(&mut self, qname: &str) -> Result<(), String> {
+        if self.compression.is_none() {
+            return self.write_qname(qname);
+        }
+
+        let labels = name_labels(qname);
+        for i in 0..labels.len() {
+            if let Some(compression) = &mut self.compression {
+                let suffix = labels[i..].join(".");
+                if let Some(&offset) = compression.offsets.get(&suffix) {
+                    return self.write_u16(0xC000 | offset as u16);
+                }
+                if compression.recurring.contains(&suffix) && self.pos <= MAX_POINTER_OFFSET {
+                    compression.offsets.insert(suffix, self.pos);
+                }
+            }
+            self.write_label(labels[i])?;
+        }
+
+        self.write(0)?;
+        Ok(())
     }
 
-    #[test]
-    fn correct_value_read_at_pos_zero_and_pos_moved_up_by_one() {
-        let mut buf = [0; PACKET_BYTES_LENGTH];
-        let zeroth_element = 1;
-        buf[0] = zeroth_element;
-        let mut packet_buffer = PacketBuffer::new(buf);
-        assert_eq!(
-            true,
-            packet_buffer.read().is_ok_and(|val| val == zeroth_element)
-        );
-        assert_eq!(1, packet_buffer.pos());
+    /// Write a label given in the presentation form used by `LabelPolicy::Escape`, so that
+    /// escaped bytes go back on the wire as the bytes they were read as
+    fn write_label(&mut self, label: &str) -> Result<(), String> {
+        let bytes = unescape_label(label)?;
+        if bytes.len() > MAX_LABEL_LENGTH {
+            let err_str = format!(
+                "Invalid label, exceeding maximum label length: max length={}, label={}",
+                MAX_LABEL_LENGTH, label
+            );
+            return Err(err_str);
+        }
+
+        self.write(bytes.len() as u8)?;
+        self.write_bytes(&bytes)
     }
 
-    #[test]
-    fn return_error_if_reading_at_index_past_end_of_buffer() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let mut packet_buffer = PacketBuffer::new(buf);
-        _ = packet_buffer.seek(PACKET_BYTES_LENGTH - 1); // seek to last byte - valid
-        _ = packet_buffer.read(); // read last byte + step forward - valid
-        let res = packet_buffer.read(); // try to read past end of buffer - invalid
-        let expected_str = "Invalid read, reading past buffer boundary: buffer length=512, pos=512";
-        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    pub fn set(&mut self, pos: usize, val: u8) -> Result<(), String> {
+        if pos >= self.buf.len() {
+            let err_str = format!(
+                "Invalid set, setting value past buffer boundary: buffer length={}, pos={}",
+                self.buf.len(),
+                pos
+            );
+            return Err(err_str);
+        }
+        self.buf[pos] = val;
+        Ok(())
     }
 
-    #[test]
-    fn get_correct_value_and_pos_not_moved_forward() {
-        let mut buf = [0; PACKET_BYTES_LENGTH];
-        let zeroth_element = 1;
-        buf[0] = zeroth_element;
-        let packet_buffer = PacketBuffer::new(buf);
-        assert_eq!(
-            true,
-            packet_buffer.get().is_ok_and(|val| val == zeroth_element)
-        );
-        assert_eq!(0, packet_buffer.pos());
+    pub fn set_u16(&mut self, pos: usize, val: u16) -> Result<(), String> {
+        self.set(pos, (val >> 8) as u8)?;
+        self.set(pos + 1, (val & 0xFF) as u8)?;
+        Ok(())
     }
+}
 
-    #[test]
-    fn return_error_if_getting_value_at_index_past_end_of_buffer() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let mut packet_buffer = PacketBuffer::new(buf);
-        _ = packet_buffer.seek(PACKET_BYTES_LENGTH - 1); // seek to last byte - valid
-        _ = packet_buffer.read(); // read last byte + step forward - valid
-        let res = packet_buffer.get(); // try to get value past end of buffer - invalid
-        let expected_str =
-            "Invalid get, getting value past buffer boundary: buffer length=512, pos=512";
-        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DnsPacket {
+    pub header: DnsHeader,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    pub authorities: Vec<DnsRecord>,
+    pub resources: Vec<DnsRecord>,
+    /// Set when parsing stopped early because the packet held fewer entries than its header
+    /// counts claimed, so the sections hold only the entries that were present
+    pub truncated_parse: bool,
+}
+
+impl Default for DnsPacket {
+    fn default() -> Self {
+        DnsPacket::new()
     }
+}
 
-    #[test]
-    fn get_correct_range_within_buffer() {
-        let buf: [u8; PACKET_BYTES_LENGTH] = core::array::from_fn(|idx| idx as u8);
-        let start = 0;
-        let len = 10;
-        let packet_buffer = PacketBuffer::new(buf);
-        let expected_slice = &buf[start..start + len];
-        assert_eq!(
-            true,
-            packet_buffer
-                .get_range(start, len)
-                .is_ok_and(|val| val == expected_slice)
-        );
+impl DnsPacket {
+    pub fn new() -> DnsPacket {
+        DnsPacket {
+            header: DnsHeader::new(),
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            resources: Vec::new(),
+            truncated_parse: false,
+        }
     }
 
-    #[test]
-    fn return_error_if_getting_range_past_end_of_buffer() {
-        let buf = [0; PACKET_BYTES_LENGTH];
-        let start = 500;
-        let len = 20;
-        let packet_buffer = PacketBuffer::new(buf);
-        let res = packet_buffer.get_range(start, len);
-        let expected_str = "Invalid range, getting range past buffer boundary: buffer length=512, start=500, len=20";
-        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    /// Build a PTR query for the `in-addr.arpa` or `ip6.arpa` name of an address, with its
+    /// transaction id taken from `ids`
+    pub fn new_reverse_query(ip: IpAddr, ids: &mut impl TxIdSource) -> DnsPacket {
+        let name = reverse_name(ip);
+        let mut packet = DnsPacket::new();
+        packet.header.id = ids.next();
+        packet.header.recursion_desired = true;
+        packet.header.questions = 1;
+        packet
+            .questions
+            .push(DnsQuestion::new(name, QueryType::PTR));
+        packet
     }
 
-    #[test]
-    fn get_correct_value_from_u16_read() {
-        let mut buf = [0; PACKET_BYTES_LENGTH];
-        let lo = 0x05;
-        let hi = 0x03;
-        let expected_u16_value = ((hi as u16) << 8) | (lo as u16);
-        buf[0] = hi;
-        buf[1] = lo;
-        let mut packet_buffer = PacketBuffer::new(buf);
-        assert_eq!(
-            true,
-            packet_buffer
-                .read_u16()
-                .is_ok_and(|val| val == expected_u16_value)
-        );
-        assert_eq!(2, packet_buffer.pos());
+    /// Cheap check of a received datagram's header before parsing it, so that traffic which
+    /// obviously isn't DNS can be dropped without allocating. Rejects datagrams shorter than a
+    /// header, with an unassigned opcode, or whose counts claim more entries than could fit.
+    pub fn is_plausible(datagram: &[u8]) -> bool {
+        // Checked before peeking so that short junk doesn't allocate peek's error string
+        if datagram.len() < HEADER_LENGTH {
+            return false;
+        }
+        let header = match DnsHeader::peek(datagram) {
+            Ok(header) => header,
+            Err(_) => return false,
+        };
+
+        // QUERY, IQUERY, STATUS, NOTIFY, UPDATE and DSO
+        if !matches!(header.opcode, 0..=2 | 4..=6) {
+            return false;
+        }
+
+        min_entries_len(&header) <= datagram.len() - HEADER_LENGTH
     }
 
-    #[test]
-    fn get_correct_value_from_u32_read() {
-        let mut buf = [0; PACKET_BYTES_LENGTH];
-        let byte_zero = 0x05;
-        let byte_one = 0x03;
-        let byte_two = 0x01;
-        let byte_three = 0x06;
-        let expected_u32_value = ((byte_three as u32) << 8 * 3)
-            | ((byte_two as u32) << 8 * 2)
-            | ((byte_one as u32) << 8)
-            | (byte_zero as u32);
-        buf[0] = byte_three;
-        buf[1] = byte_two;
+    /// Parse a packet with `options` in place of the buffer's own parse options, which are
+    /// restored afterwards
+    pub fn from_buffer_with_options(
+        buffer: &mut PacketBuffer,
+        options: ParseOptions,
+    ) -> Result<DnsPacket, String> {
+        let buffer_options = buffer.parse_options();
+        buffer.set_parse_options(options);
+        let res = DnsPacket::from_buffer(buffer);
+        buffer.set_parse_options(buffer_options);
+        res
+    }
+
+    pub fn from_buffer(buffer: &mut PacketBuffer) -> Result<DnsPacket, String> {
+        let mut packet = DnsPacket::new();
+        packet.header = DnsHeader::from_buffer(buffer)?;
+
+        if min_entries_len(&packet.header) > buffer.remaining() {
+            let err_str = format!(
+                "Invalid packet, header counts claim more entries than can fit in remaining bytes: questions={}, answers={}, authorities={}, resources={}, remaining={}",
+                packet.header.questions,
+                packet.header.answers,
+                packet.header.authoritative_entries,
+                packet.header.resource_entries,
+                buffer.remaining()
+            );
+            return Err(err_str);
+        }
+
+        for _ in 0..packet.header.questions {
+            packet.questions.push(DnsQuestion::from_buffer(buffer)?);
+        }
+        for _ in 0..packet.header.answers {
+            packet.answers.push(DnsRecord::from_buffer(buffer)?);
+        }
+        for _ in 0..packet.header.authoritative_entries {
+            packet.authorities.push(DnsRecord::from_buffer(buffer)?);
+        }
+        for _ in 0..packet.header.resource_entries {
+            packet.resources.push(DnsRecord::from_buffer(buffer)?);
+        }
+
+        Ok(packet)
+    }
+
+    /// Parse a packet, additionally rejecting it if the declared records don't account for all
+    /// `len` received bytes. Only zero padding is allowed after the last record.
+    pub fn from_buffer_strict(buffer: &mut PacketBuffer, len: usize) -> Result<DnsPacket, String> {
+        if len > buffer.buf.len() {
+            let err_str = format!(
+                "Invalid length, received length longer than buffer: buffer length={}, len={}",
+                buffer.buf.len(),
+                len
+            );
+            return Err(err_str);
+        }
+
+        let packet = DnsPacket::from_buffer(buffer)?;
+
+        if buffer.pos > len {
+            let err_str = format!(
+                "Invalid packet, records extend past received length: received length={}, pos={}",
+                len, buffer.pos
+            );
+            return Err(err_str);
+        }
+        if buffer.buf[buffer.pos..len].iter().any(|byte| *byte != 0) {
+            let err_str = format!(
+                "Invalid packet, trailing bytes after last record: received length={}, pos={}",
+                len, buffer.pos
+            );
+            return Err(err_str);
+        }
+
+        Ok(packet)
+    }
+
+    /// Parse the first `len` bytes of a packet, tolerating header counts that claim more
+    /// entries than are present. Parsing stops at the first entry that doesn't fit within
+    /// `len`, keeping the entries before it and setting `truncated_parse`. Entries that are
+    /// malformed within `len` are still an error.
+    pub fn from_buffer_tolerant(
+        buffer: &mut PacketBuffer,
+        len: usize,
+    ) -> Result<DnsPacket, String> {
+        if len > buffer.buf.len() {
+            let err_str = format!(
+                "Invalid length, received length longer than buffer: buffer length={}, len={}",
+                buffer.buf.len(),
+                len
+            );
+            return Err(err_str);
+        }
+
+        let mut packet = DnsPacket::new();
+        packet.header = DnsHeader::from_buffer(buffer)?;
+
+        for _ in 0..packet.header.questions {
+            match parse_within(buffer, len, DnsQuestion::from_buffer)? {
+                Some(question) => packet.questions.push(question),
+                None => {
+                    packet.truncated_parse = true;
+                    return Ok(packet);
+                }
+            }
+        }
+        'sections: for (count, section) in [
+            (packet.header.answers, &mut packet.answers),
+            (packet.header.authoritative_entries, &mut packet.authorities),
+            (packet.header.resource_entries, &mut packet.resources),
+        ] {
+            for _ in 0..count {
+                match parse_within(buffer, len, DnsRecord::from_buffer)? {
+                    Some(record) => section.push(record),
+                    None => {
+                        packet.truncated_parse = true;
+                        break 'sections;
+                    }
+                }
+            }
+        }
+
+        Ok(packet)
+    }
+
+    /// Write the packet, with the header counts taken from the number of entries in each
+    /// section rather than from the header itself
+    pub fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        let mut header = self.header.clone();
+        header.questions = self.questions.len() as u16;
+        header.answers = self.answers.len() as u16;
+        header.authoritative_entries = self.authorities.len() as u16;
+        header.resource_entries = self.resources.len() as u16;
+        header.write(buffer)?;
+
+        for question in &self.questions {
+            question.write(buffer)?;
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.resources)
+        {
+            record.write(buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the packet as with `write`, compressing names that occur more than once. A first
+    /// pass finds which names (and name suffixes) recur, so only those are remembered as
+    /// pointer targets while writing.
+    pub fn write_compressed(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let names = self
+            .questions
+            .iter()
+            .map(|question| question.name.as_str())
+            .chain(
+                self.answers
+                    .iter()
+                    .chain(&self.authorities)
+                    .chain(&self.resources)
+                    .flat_map(|record| record.compressible_names()),
+            );
+        for name in names {
+            let labels = name_labels(name);
+            for i in 0..labels.len() {
+                *counts.entry(labels[i..].join(".")).or_default() += 1;
+            }
+        }
+
+        buffer.compression = Some(NameCompression {
+            recurring: counts
+                .into_iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(suffix, _)| suffix)
+                .collect(),
+            offsets: HashMap::new(),
+        });
+        let res = self.write(buffer);
+        buffer.compression = None;
+        res
+    }
+
+    /// Write the packet as with `write`, but within `max_len` bytes, as for a UDP response capped
+    /// below the requestor's payload size. If the whole packet doesn't fit, records are dropped
+    /// from the end of the additional, authority and then answer sections until it does, and
+    /// `truncated_message` is set so the requestor retries over TCP. Any OPT record is kept.
+    pub fn write_truncated(&self, buffer: &mut PacketBuffer, max_len: usize) -> Result<(), String> {
+        let mut scratch = PacketBuffer::with_capacity(u16::MAX as usize);
+        self.write(&mut scratch)?;
+        if scratch.pos() <= max_len {
+            return self.write(buffer);
+        }
+
+        let mut truncated = DnsPacket::new();
+        truncated.header = self.header.clone();
+        truncated.header.truncated_message = true;
+        truncated.questions = self.questions.clone();
+        truncated.resources = self
+            .resources
+            .iter()
+            .filter(|record| matches!(record, DnsRecord::OPT { .. }))
+            .cloned()
+            .collect();
+        scratch.seek(0)?;
+        truncated.write(&mut scratch)?;
+        let mut len = scratch.pos();
+        if len > max_len {
+            let err_str = format!(
+                "Invalid max length, question and OPT record don't fit: max length={}, len={}",
+                max_len, len
+            );
+            return Err(err_str);
+        }
+
+        let mut kept_resources = Vec::new();
+        'sections: for (section, kept) in [
+            (&self.answers, &mut truncated.answers),
+            (&self.authorities, &mut truncated.authorities),
+            (&self.resources, &mut kept_resources),
+        ] {
+            for record in section {
+                if matches!(record, DnsRecord::OPT { .. }) {
+                    continue;
+                }
+                scratch.seek(0)?;
+                record.write(&mut scratch)?;
+                if len + scratch.pos() > max_len {
+                    break 'sections;
+                }
+                len += scratch.pos();
+                kept.push(record.clone());
+            }
+        }
+        kept_resources.append(&mut truncated.resources);
+        truncated.resources = kept_resources;
+
+        truncated.write(buffer)
+    }
+
+    /// Remove records within each section that duplicate an earlier record in that section
+    /// (same name, type and data), keeping the first occurrence
+    pub fn dedup_records(&mut self) {
+        for section in [
+            &mut self.answers,
+            &mut self.authorities,
+            &mut self.resources,
+        ] {
+            let mut deduped: Vec<DnsRecord> = Vec::with_capacity(section.len());
+            for record in section.drain(..) {
+                if !deduped.iter().any(|kept| kept.is_duplicate_of(&record)) {
+                    deduped.push(record);
+                }
+            }
+            *section = deduped;
+        }
+    }
+
+    /// Rotate each set of A or AAAA answers sharing a name by `n` places, for round-robin load
+    /// balancing across the addresses when `n` counts up with each response. Each set's records
+    /// stay in the positions the set took up, so other answers such as a CNAME leading to them
+    /// keep their place.
+    pub fn rotate_answers(&mut self, n: usize) {
+        let mut rrsets: Vec<((String, QueryType), Vec<usize>)> = Vec::new();
+        for (idx, record) in self.answers.iter().enumerate() {
+            if !matches!(record, DnsRecord::A { .. } | DnsRecord::AAAA { .. }) {
+                continue;
+            }
+            let key = (record.name().to_ascii_lowercase(), record.qtype());
+            match rrsets.iter_mut().find(|(rrset_key, _)| *rrset_key == key) {
+                Some((_, positions)) => positions.push(idx),
+                None => rrsets.push((key, vec![idx])),
+            }
+        }
+
+        for (_, positions) in rrsets {
+            let mut records: Vec<DnsRecord> = positions
+                .iter()
+                .map(|&idx| self.answers[idx].clone())
+                .collect();
+            let len = records.len();
+            records.rotate_left(n % len);
+            for (idx, record) in positions.into_iter().zip(records) {
+                self.answers[idx] = record;
+            }
+        }
+    }
+
+    /// Order the answers so that CNAMEs come first, following the chain from the question's
+    /// name, ahead of the records at its end. CNAMEs not on that chain follow it, and the order
+    /// among all other answers is kept.
+    pub fn order_cname_first(&mut self) {
+        let mut remaining = std::mem::take(&mut self.answers);
+        let mut ordered = Vec::with_capacity(remaining.len());
+        if let Some(question) = self.questions.first() {
+            let mut name = question.name.clone();
+            while let Some(idx) = remaining.iter().position(|record| {
+                matches!(record, DnsRecord::CNAME { .. })
+                    && record.name().eq_ignore_ascii_case(&name)
+            }) {
+                let record = remaining.remove(idx);
+                if let DnsRecord::CNAME { ref host, .. } = record {
+                    name = host.clone();
+                }
+                ordered.push(record);
+            }
+        }
+
+        let (cnames, others): (Vec<DnsRecord>, Vec<DnsRecord>) = remaining
+            .into_iter()
+            .partition(|record| matches!(record, DnsRecord::CNAME { .. }));
+        ordered.extend(cnames);
+        ordered.extend(others);
+        self.answers = ordered;
+    }
+
+    /// Smallest TTL across the answer and authority sections, which bounds how long the whole
+    /// response may be cached. None if both sections are empty.
+    pub fn min_ttl(&self) -> Option<u32> {
+        self.answers
+            .iter()
+            .chain(&self.authorities)
+            .map(|record| record.ttl())
+            .min()
+    }
+
+    /// Parse a packet starting at the buffer's current position, also returning how many bytes
+    /// it took up so that a following message in the same buffer can be parsed after it. The
+    /// message is parsed on its own, so its compression pointers are offsets from its start.
+    pub fn from_buffer_consumed(buffer: &mut PacketBuffer) -> Result<(DnsPacket, usize), String> {
+        let start = buffer.pos;
+        let len = buffer.remaining();
+        let mut message = PacketBuffer::from_message(&buffer.buf[start..start + len]);
+        message.options = buffer.options;
+
+        let packet = DnsPacket::from_buffer(&mut message)?;
+        buffer.pos = start + message.pos;
+        Ok((packet, message.pos))
+    }
+
+    /// Build a query to send upstream carrying the same questions, taking a fresh transaction
+    /// id from `ids`. Recursion is requested and any answers are dropped. From the additional
+    /// section only the OPT record is kept, so the client's EDNS settings carry over.
+    pub fn to_forwarded_query(&self, ids: &mut impl TxIdSource) -> DnsPacket {
+        let mut query = DnsPacket::new();
+        query.header.id = ids.next();
+        query.header.opcode = self.header.opcode;
+        query.header.recursion_desired = true;
+        query.header.checking_disabled = self.header.checking_disabled;
+        query.questions = self.questions.clone();
+        query.resources = self
+            .resources
+            .iter()
+            .filter(|record| matches!(record, DnsRecord::OPT { .. }))
+            .cloned()
+            .collect();
+        query
+    }
+
+    /// Build the response to send back to a client from an upstream's response to its
+    /// forwarded `query`, taking the id and CD bit from the client's query. AD is cleared, as
+    /// this server doesn't validate DNSSEC and so can't vouch for the answer it relays.
+    pub fn to_forwarded_response(&self, query: &DnsPacket) -> DnsPacket {
+        let mut response = self.clone();
+        response.header.id = query.header.id;
+        response.header.authed_data = false;
+        response.header.checking_disabled = query.header.checking_disabled;
+        response
+    }
+
+    /// Empty authoritative NOERROR response to this query, for answers this server gives
+    /// itself
+    fn local_response(&self) -> DnsPacket {
+        let mut response = DnsPacket::new();
+        response.header.id = self.header.id;
+        response.header.response = true;
+        response.header.opcode = self.header.opcode;
+        response.header.recursion_desired = self.header.recursion_desired;
+        response.header.authoritative_answer = true;
+        response.header.rescode = ResultCode::NoError;
+        response.questions = self.questions.clone();
+        response
+    }
+
+    /// Build a Refused response to a query asking for any of `refused_qtypes`, such as ANY to
+    /// avoid being used for amplification. Gives `None` when the query can be answered as
+    /// normal.
+    pub fn refused_response(&self, refused_qtypes: &HashSet<QueryType>) -> Option<DnsPacket> {
+        if !self
+            .questions
+            .iter()
+            .any(|question| refused_qtypes.contains(&question.qtype))
+        {
+            return None;
+        }
+
+        let mut response = DnsPacket::new();
+        response.header.id = self.header.id;
+        response.header.response = true;
+        response.header.opcode = self.header.opcode;
+        response.header.recursion_desired = self.header.recursion_desired;
+        response.header.rescode = ResultCode::Refused;
+        response.questions = self.questions.clone();
+        Some(response)
+    }
+
+    /// Answer a query for `localhost` or a name below it, or for the reverse name of 127.0.0.1
+    /// or ::1, without asking upstream (RFC 6761 section 6.3). `localhost` resolves to
+    /// 127.0.0.1 and ::1, and other record types get NODATA. Gives `None` for any other query.
+    pub fn special_use_answer(&self) -> Option<DnsPacket> {
+        let [question] = &self.questions[..] else {
+            return None;
+        };
+        let answer = if in_zone(&question.name, "localhost") {
+            match question.qtype {
+                QueryType::A => Some(DnsRecord::A {
+                    domain: question.name.clone(),
+                    addr: Ipv4Addr::LOCALHOST,
+                    class: question.class,
+                    ttl: LOCALHOST_TTL,
+                }),
+                QueryType::AAAA => Some(DnsRecord::AAAA {
+                    domain: question.name.clone(),
+                    addr: Ipv6Addr::LOCALHOST,
+                    class: question.class,
+                    ttl: LOCALHOST_TTL,
+                }),
+                _ => None,
+            }
+        } else if [
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ]
+        .into_iter()
+        .any(|ip| question.name.eq_ignore_ascii_case(&reverse_name(ip)))
+        {
+            match question.qtype {
+                QueryType::PTR => Some(DnsRecord::PTR {
+                    domain: question.name.clone(),
+                    host: "localhost".to_string(),
+                    class: question.class,
+                    ttl: LOCALHOST_TTL,
+                }),
+                _ => None,
+            }
+        } else {
+            return None;
+        };
+
+        let mut response = self.local_response();
+        response.answers.extend(answer);
+        Some(response)
+    }
+
+    /// Answer a health probe for `health_qname` with NOERROR and a static A record of `addr`,
+    /// without consulting the cache or upstream. Probes for other record types get NODATA.
+    /// Gives `None` for queries of any other name.
+    pub fn health_response(&self, health_qname: &str, addr: Ipv4Addr) -> Option<DnsPacket> {
+        let [question] = &self.questions[..] else {
+            return None;
+        };
+        if !question
+            .name
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(health_qname.trim_end_matches('.'))
+        {
+            return None;
+        }
+
+        let mut response = self.local_response();
+        if question.qtype == QueryType::A {
+            response.answers.push(DnsRecord::A {
+                domain: question.name.clone(),
+                addr,
+                class: question.class,
+                ttl: HEALTH_TTL,
+            });
+        }
+        Some(response)
+    }
+
+    /// Build the response a secondary sends to acknowledge a NOTIFY from its primary, echoing
+    /// the question with AA set (RFC 1996 section 4.7). Gives `None` when the packet isn't a
+    /// NOTIFY request.
+    pub fn notify_response(&self) -> Option<DnsPacket> {
+        if self.header.opcode != OPCODE_NOTIFY || self.header.response {
+            return None;
+        }
+
+        let mut response = DnsPacket::new();
+        response.header.id = self.header.id;
+        response.header.response = true;
+        response.header.opcode = OPCODE_NOTIFY;
+        response.header.authoritative_answer = true;
+        response.questions = self.questions.clone();
+        Some(response)
+    }
+
+    /// Acknowledge a NOTIFY as with `notify_response`, calling `refresh` with each zone that
+    /// the primary says has changed so that its serial can be checked
+    pub fn handle_notify(&self, mut refresh: impl FnMut(&str)) -> Option<DnsPacket> {
+        let response = self.notify_response()?;
+        for question in &self.questions {
+            if question.qtype == QueryType::SOA {
+                refresh(&question.name);
+            }
+        }
+        Some(response)
+    }
+
+    /// Build the response to a query for a blocked name, answering in the way given by `block`.
+    /// When the query uses EDNS, the response carries a Blocked extended error (RFC 8914).
+    pub fn blocked_response(&self, block: BlockResponse) -> DnsPacket {
+        let mut response = DnsPacket::new();
+        response.header.id = self.header.id;
+        response.header.response = true;
+        response.header.opcode = self.header.opcode;
+        response.header.recursion_desired = self.header.recursion_desired;
+        response.questions = self.questions.clone();
+        match block {
+            BlockResponse::NxDomain => response.header.rescode = ResultCode::NxDomain,
+            BlockResponse::NoData => response.header.rescode = ResultCode::NoError,
+            BlockResponse::Refused => response.header.rescode = ResultCode::Refused,
+            BlockResponse::Sinkhole(addr) => {
+                response.header.rescode = ResultCode::NoError;
+                for question in &self.questions {
+                    let record = match (question.qtype, addr) {
+                        (QueryType::A, IpAddr::V4(addr)) => DnsRecord::A {
+                            domain: question.name.clone(),
+                            addr,
+                            class: question.class,
+                            ttl: SINKHOLE_TTL,
+                        },
+                        (QueryType::AAAA, IpAddr::V6(addr)) => DnsRecord::AAAA {
+                            domain: question.name.clone(),
+                            addr,
+                            class: question.class,
+                            ttl: SINKHOLE_TTL,
+                        },
+                        _ => continue,
+                    };
+                    response.answers.push(record);
+                }
+            }
+        }
+
+        let query_has_opt = self
+            .resources
+            .iter()
+            .any(|record| matches!(record, DnsRecord::OPT { .. }));
+        if query_has_opt {
+            attach_extended_error(
+                &mut response,
+                ExtendedError::new(INFO_CODE_BLOCKED, String::new()),
+            );
+        }
+        response
+    }
+
+    /// Add the A and AAAA records from `known`, such as a zone's records or cached ones, for the
+    /// hosts that MX, NS and SRV answers point at, so that clients don't have to look them up
+    /// separately. The records go ahead of any OPT or TSIG record in the additional section,
+    /// and records already there aren't added again.
+    pub fn add_additional_addresses(&mut self, known: &[DnsRecord]) {
+        let targets: Vec<String> = self
+            .answers
+            .iter()
+            .filter_map(|record| record.target_host())
+            .map(|host| host.to_string())
+            .collect();
+        let mut insert_pos = self
+            .resources
+            .iter()
+            .position(|record| matches!(record, DnsRecord::OPT { .. } | DnsRecord::TSIG { .. }))
+            .unwrap_or(self.resources.len());
+
+        for record in known {
+            if !matches!(record, DnsRecord::A { .. } | DnsRecord::AAAA { .. }) {
+                continue;
+            }
+            if !targets
+                .iter()
+                .any(|target| target.eq_ignore_ascii_case(record.name()))
+            {
+                continue;
+            }
+            if self
+                .resources
+                .iter()
+                .any(|existing| existing.is_duplicate_of(record))
+            {
+                continue;
+            }
+            self.resources.insert(insert_pos, record.clone());
+            insert_pos += 1;
+        }
+    }
+
+    /// Whether two packets are the same apart from their transaction ids, such as a cached
+    /// response and a fresh one for the same query
+    pub fn semantically_equal(&self, other: &DnsPacket) -> bool {
+        let other_header = DnsHeader {
+            id: self.header.id,
+            ..other.header.clone()
+        };
+        self.header == other_header
+            && self.questions == other.questions
+            && self.answers == other.answers
+            && self.authorities == other.authorities
+            && self.resources == other.resources
+            && self.truncated_parse == other.truncated_parse
+    }
+
+    /// Questions and records in presentation format, laid out the way dig prints them with a
+    /// comment line heading each section that has entries
+    pub fn to_presentation(&self) -> String {
+        let mut sections = Vec::new();
+        if !self.questions.is_empty() {
+            let lines: Vec<String> = self
+                .questions
+                .iter()
+                .map(|question| {
+                    format!(
+                        ";{} {} {}",
+                        absolute_name(&question.name),
+                        class_mnemonic(question.class),
+                        question.qtype
+                    )
+                })
+                .collect();
+            sections.push(format!(";; QUESTION SECTION:\n{}", lines.join("\n")));
+        }
+        for (title, records) in [
+            ("ANSWER", &self.answers),
+            ("AUTHORITY", &self.authorities),
+            ("ADDITIONAL", &self.resources),
+        ] {
+            if records.is_empty() {
+                continue;
+            }
+            let lines: Vec<String> = records.iter().map(DnsRecord::to_presentation).collect();
+            sections.push(format!(";; {} SECTION:\n{}", title, lines.join("\n")));
+        }
+        sections.join("\n\n")
+    }
+}
+
+/// Fewest bytes the entries a header counts could take up after the header
+fn min_entries_len(header: &DnsHeader) -> usize {
+    header.questions as usize * MIN_QUESTION_LENGTH
+        + (header.answers as usize
+            + header.authoritative_entries as usize
+            + header.resource_entries as usize)
+            * MIN_RECORD_LENGTH
+}
+
+/// Append a label to `qname` in the presentation form used by `LabelPolicy::Escape`
+fn push_escaped_label(qname: &mut String, label: &[u8]) {
+    let plain = |byte: &u8| matches!(byte, 0x21..=0x7E) && *byte != b'.' && *byte != b'\\';
+    if label.iter().all(plain) {
+        // Printable ASCII is always valid UTF-8
+        qname.push_str(std::str::from_utf8(label).unwrap_or_default());
+        return;
+    }
+
+    for &byte in label {
+        match byte {
+            b'.' | b'\\' => {
+                qname.push('\\');
+                qname.push(byte as char);
+            }
+            0x21..=0x7E => qname.push(byte as char),
+            _ => {
+                _ = write!(qname, "\\{:03}", byte);
+            }
+        }
+    }
+}
+
+/// Labels of a name, split at each dot that isn't escaped with a backslash
+fn name_labels(name: &str) -> Vec<&str> {
+    let mut labels = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, byte) in name.bytes().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match byte {
+            b'\\' => escaped = true,
+            b'.' => {
+                if i > start {
+                    labels.push(&name[start..i]);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < name.len() {
+        labels.push(&name[start..]);
+    }
+    labels
+}
+
+/// Bytes of a label in the form written by `push_escaped_label`, with `\DDD` and characters
+/// escaped by a backslash turned back into the bytes they stand for
+fn unescape_label(label: &str) -> Result<Vec<u8>, String> {
+    let bytes = label.as_bytes();
+    if !bytes.contains(&b'\\') {
+        return Ok(bytes.to_vec());
+    }
+
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            unescaped.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        match bytes.get(i + 1..i + 4) {
+            Some(digits) if digits.iter().all(u8::is_ascii_digit) => {
+                let val = digits
+                    .iter()
+                    .fold(0u16, |val, digit| val * 10 + (digit - b'0') as u16);
+                if val > 0xFF {
+                    let err_str = format!(
+                        "Invalid label, escaped byte out of range: label={}, byte={}",
+                        label, val
+                    );
+                    return Err(err_str);
+                }
+                unescaped.push(val as u8);
+                i += 4;
+            }
+            _ => match bytes.get(i + 1) {
+                Some(&byte) => {
+                    unescaped.push(byte);
+                    i += 2;
+                }
+                None => {
+                    let err_str = format!("Invalid label, ends in a backslash: label={}", label);
+                    return Err(err_str);
+                }
+            },
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Parse an entry with reads bounded to the first `len` bytes of the buffer, giving `None`
+/// if the entry doesn't fit within them
+/// The `in-addr.arpa` or `ip6.arpa` name that PTR records for an address are found at
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(addr) => {
+            let labels: Vec<String> = addr.octets().iter().rev().map(|o| o.to_string()).collect();
+            format!("{}.in-addr.arpa", labels.join("."))
+        }
+        IpAddr::V6(addr) => {
+            let labels: Vec<String> = addr
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|o| [o & 0x0F, o >> 4])
+                .map(|nibble| format!("{:x}", nibble))
+                .collect();
+            format!("{}.ip6.arpa", labels.join("."))
+        }
+    }
+}
+
+fn parse_within<T>(
+    buffer: &mut PacketBuffer,
+    len: usize,
+    parse: fn(&mut PacketBuffer) -> Result<T, String>,
+) -> Result<Option<T>, String> {
+    if buffer.pos >= len {
+        return Ok(None);
+    }
+
+    let full_len = buffer.len;
+    buffer.len = len.min(full_len);
+    buffer.overran.set(false);
+    let res = parse(buffer);
+    buffer.len = full_len;
+    match res {
+        Ok(entry) => Ok(Some(entry)),
+        Err(_) if buffer.overran.get() => Ok(None),
+        Err(err_str) => Err(err_str),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::ResultCode;
+    use crate::record::{CLASS_CH, CLASS_IN};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn current_position_within_new_packet_buffer_is_zero() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(0, packet_buffer.pos());
+    }
+
+    #[test]
+    fn buffer_from_reader_contains_read_bytes() {
+        let bytes = [0x12, 0x34, 0x56, 0x78, 0x9a];
+        let mut reader = std::io::Cursor::new(bytes);
+        let res = PacketBuffer::from_reader(&mut reader, bytes.len());
+        assert_eq!(true, res.is_ok());
+        let packet_buffer = res.unwrap();
+        assert_eq!(0, packet_buffer.pos());
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, bytes.len())
+                .is_ok_and(|val| val == bytes)
+        );
+        let expected_str =
+            "Invalid range, getting range past buffer boundary: buffer length=5, start=5, len=1";
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(bytes.len(), 1)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn buffer_from_message_longer_than_udp_packet_is_readable_to_its_end() {
+        let bytes: Vec<u8> = (0..600).map(|idx| idx as u8).collect();
+        let mut packet_buffer = PacketBuffer::from_message(&bytes);
+        assert_eq!(bytes.len(), packet_buffer.len());
+        assert_eq!(true, packet_buffer.seek(599).is_ok());
+        assert_eq!(true, packet_buffer.read().is_ok_and(|val| val == 87));
+        let expected_str =
+            "Invalid write, writing past buffer boundary: buffer length=600, pos=600";
+        assert_eq!(
+            true,
+            packet_buffer
+                .write(0)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_counts_claim_more_records_than_read_from_reader() {
+        let mut packet = DnsPacket::new();
+        packet.answers.push(DnsRecord::A {
+            domain: "example.com".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 1),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos();
+        // ANCOUNT of 3 with only one answer present
+        packet_buffer.set_u16(6, 3).unwrap();
+        let bytes = packet_buffer.get_range(0, len).unwrap().to_vec();
+
+        let mut reader = std::io::Cursor::new(bytes);
+        let mut res = PacketBuffer::from_reader(&mut reader, len).unwrap();
+        let expected_str = "Invalid packet, header counts claim more entries than can fit in remaining bytes: questions=0, answers=3, authorities=0, resources=0, remaining=27";
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer(&mut res).is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn buffer_received_from_socket_contains_datagram_and_sender() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let bytes = [0x12, 0x34, 0x56, 0x78, 0x9a];
+        sender
+            .send_to(&bytes, receiver.local_addr().unwrap())
+            .unwrap();
+
+        let (packet_buffer, src) = PacketBuffer::recv_from(&receiver).unwrap();
+        assert_eq!(sender.local_addr().unwrap(), src);
+        assert_eq!(0, packet_buffer.pos());
+        assert_eq!(bytes.len(), packet_buffer.len());
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, bytes.len())
+                .is_ok_and(|val| val == bytes)
+        );
+    }
+
+    #[test]
+    fn return_error_if_reading_past_end_of_received_datagram() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(&[0x12, 0x34, 0x56], receiver.local_addr().unwrap())
+            .unwrap();
+
+        let (mut packet_buffer, _) = PacketBuffer::recv_from(&receiver).unwrap();
+        assert_eq!(
+            true,
+            packet_buffer.read_u16().is_ok_and(|val| val == 0x1234)
+        );
+        assert_eq!(true, packet_buffer.read_u16().is_err());
+        let expected_str =
+            "Invalid range, getting range past buffer boundary: buffer length=3, start=2, len=2";
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(2, 2)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+        assert_eq!(0, packet_buffer.remaining());
+    }
+
+    #[test]
+    fn return_error_if_reader_has_fewer_bytes_than_len() {
+        let bytes = [0x12, 0x34];
+        let mut reader = std::io::Cursor::new(bytes);
+        let res = PacketBuffer::from_reader(&mut reader, 4);
+        let expected_str = "Failed to read 4 bytes from reader: failed to fill whole buffer";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_reading_more_bytes_than_buffer_length() {
+        let bytes = [0; PACKET_BYTES_LENGTH + 1];
+        let mut reader = std::io::Cursor::new(bytes);
+        let res = PacketBuffer::from_reader(&mut reader, bytes.len());
+        let expected_str =
+            "Invalid length, reading more bytes than buffer can hold: buffer length=512, len=513";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn remaining_bytes_decrease_as_pos_moves_forward() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(PACKET_BYTES_LENGTH, packet_buffer.remaining());
+        _ = packet_buffer.seek(500);
+        assert_eq!(12, packet_buffer.remaining());
+        _ = packet_buffer.read_bytes(12);
+        assert_eq!(0, packet_buffer.remaining());
+    }
+
+    #[test]
+    fn step_position_forward_in_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let step = 5;
+        let res = packet_buffer.step(step);
+        assert_eq!(true, res.is_ok());
+        assert_eq!(step, packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_stepping_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let invalid_step = PACKET_BYTES_LENGTH + 1;
+        let res = packet_buffer.step(invalid_step);
+        let expected_err_str =
+            "Invalid step, stepping past buffer boundary: buffer length=512, pos=0, step=513";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_err_str));
+    }
+
+    #[test]
+    fn seek_to_position_within_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let new_pos = 51;
+        let res = packet_buffer.seek(new_pos);
+        assert_eq!(true, res.is_ok());
+        assert_eq!(new_pos, packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_seeking_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let invalid_pos = 600;
+        let res = packet_buffer.seek(invalid_pos);
+        let expected_str =
+            "Invalid seek, seeking past buffer boundary: buffer length=512, seek=600";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn correct_value_read_at_pos_zero_and_pos_moved_up_by_one() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let zeroth_element = 1;
+        buf[0] = zeroth_element;
+        let mut packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(
+            true,
+            packet_buffer.read().is_ok_and(|val| val == zeroth_element)
+        );
+        assert_eq!(1, packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_reading_at_index_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(PACKET_BYTES_LENGTH - 1); // seek to last byte - valid
+        _ = packet_buffer.read(); // read last byte + step forward - valid
+        let res = packet_buffer.read(); // try to read past end of buffer - invalid
+        let expected_str = "Invalid read, reading past buffer boundary: buffer length=512, pos=512";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn get_correct_value_and_pos_not_moved_forward() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let zeroth_element = 1;
+        buf[0] = zeroth_element;
+        let packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(
+            true,
+            packet_buffer.get().is_ok_and(|val| val == zeroth_element)
+        );
+        assert_eq!(0, packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_getting_value_at_index_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(PACKET_BYTES_LENGTH - 1); // seek to last byte - valid
+        _ = packet_buffer.read(); // read last byte + step forward - valid
+        let res = packet_buffer.get(); // try to get value past end of buffer - invalid
+        let expected_str =
+            "Invalid get, getting value past buffer boundary: buffer length=512, pos=512";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn get_correct_range_within_buffer() {
+        let buf: [u8; PACKET_BYTES_LENGTH] = core::array::from_fn(|idx| idx as u8);
+        let start = 0;
+        let len = 10;
+        let packet_buffer = PacketBuffer::new(buf);
+        let expected_slice = &buf[start..start + len];
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(start, len)
+                .is_ok_and(|val| val == expected_slice)
+        );
+    }
+
+    #[test]
+    fn return_error_if_getting_range_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let start = 500;
+        let len = 20;
+        let packet_buffer = PacketBuffer::new(buf);
+        let res = packet_buffer.get_range(start, len);
+        let expected_str = "Invalid range, getting range past buffer boundary: buffer length=512, start=500, len=20";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn get_correct_value_from_u16_read() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let lo = 0x05;
+        let hi = 0x03;
+        let expected_u16_value = ((hi as u16) << 8) | (lo as u16);
+        buf[0] = hi;
+        buf[1] = lo;
+        let mut packet_buffer = PacketBuffer::new(buf);
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_u16()
+                .is_ok_and(|val| val == expected_u16_value)
+        );
+        assert_eq!(2, packet_buffer.pos());
+    }
+
+    #[test]
+    fn get_correct_value_from_u32_read() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let byte_zero = 0x05;
+        let byte_one = 0x03;
+        let byte_two = 0x01;
+        let byte_three = 0x06;
+        let expected_u32_value = ((byte_three as u32) << (8 * 3))
+            | ((byte_two as u32) << (8 * 2))
+            | ((byte_one as u32) << 8)
+            | (byte_zero as u32);
+        buf[0] = byte_three;
+        buf[1] = byte_two;
         buf[2] = byte_one;
         buf[3] = byte_zero;
         let mut packet_buffer = PacketBuffer::new(buf);
         assert_eq!(
             true,
             packet_buffer
-                .read_u32()
-                .is_ok_and(|val| val == expected_u32_value)
+                .read_u32()
+                .is_ok_and(|val| val == expected_u32_value)
+        );
+        assert_eq!(4, packet_buffer.pos());
+    }
+
+    #[test]
+    fn correct_value_written_at_pos_zero_and_pos_moved_up_by_one() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let val = 7;
+        assert_eq!(true, packet_buffer.write(val).is_ok());
+        assert_eq!(1, packet_buffer.pos());
+        _ = packet_buffer.seek(0);
+        assert_eq!(true, packet_buffer.get().is_ok_and(|res| res == val));
+    }
+
+    #[test]
+    fn return_error_if_writing_at_index_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(PACKET_BYTES_LENGTH - 1); // seek to last byte - valid
+        _ = packet_buffer.write(1); // write last byte + step forward - valid
+        let res = packet_buffer.write(1); // try to write past end of buffer - invalid
+        let expected_str =
+            "Invalid write, writing past buffer boundary: buffer length=512, pos=512";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn u16_write_is_read_back_as_same_value() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let val = 0x0305;
+        assert_eq!(true, packet_buffer.write_u16(val).is_ok());
+        assert_eq!(2, packet_buffer.pos());
+        _ = packet_buffer.seek(0);
+        assert_eq!(true, packet_buffer.read_u16().is_ok_and(|res| res == val));
+    }
+
+    #[test]
+    fn u32_write_is_read_back_as_same_value() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let val = 0x06010305;
+        assert_eq!(true, packet_buffer.write_u32(val).is_ok());
+        assert_eq!(4, packet_buffer.pos());
+        _ = packet_buffer.seek(0);
+        assert_eq!(true, packet_buffer.read_u32().is_ok_and(|res| res == val));
+    }
+
+    #[test]
+    fn read_bytes_returns_bytes_and_moves_pos_forward() {
+        let buf: [u8; PACKET_BYTES_LENGTH] = core::array::from_fn(|idx| idx as u8);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(3);
+        let res = packet_buffer.read_bytes(4);
+        assert_eq!(true, res.is_ok_and(|val| val == [3, 4, 5, 6]));
+        assert_eq!(7, packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_reading_bytes_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(510);
+        let res = packet_buffer.read_bytes(3);
+        let expected_str =
+            "Invalid read, reading bytes past buffer boundary: buffer length=512, pos=510, len=3";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn read_uncompressed_qname() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x06, b'g', b'o', b'o', b'g', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        ];
+        buf[..qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let res = packet_buffer.read_qname();
+        assert_eq!(true, res.is_ok_and(|val| val == "google.com"));
+        assert_eq!(qname_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn read_compressed_qname_and_pos_moved_past_pointer() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x06, b'g', b'o', b'o', b'g', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // google.com
+            0x03, b'w', b'w', b'w', 0xC0, 0x0C, // www + pointer to google.com
+        ];
+        buf[12..12 + qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(24);
+        let res = packet_buffer.read_qname();
+        assert_eq!(true, res.is_ok_and(|val| val == "www.google.com"));
+        assert_eq!(12 + qname_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn qname_read_into_reused_string_matches_read_qname() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x06, b'g', b'o', b'o', b'g', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // google.com
+            0x03, b'w', b'w', b'w', 0xC0, 0x0C, // www + pointer to google.com
+            0x02, b'n', b's', 0xC0, 0x13, // ns + pointer to com
+        ];
+        buf[12..12 + qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(12);
+        let mut expected_names = Vec::new();
+        for _ in 0..3 {
+            expected_names.push(packet_buffer.read_qname().unwrap());
+        }
+        let expected_pos = packet_buffer.pos();
+
+        _ = packet_buffer.seek(12);
+        let mut qname = "stale contents that should be cleared".to_string();
+        for expected_name in expected_names {
+            assert_eq!(true, packet_buffer.read_qname_into(&mut qname).is_ok());
+            assert_eq!(expected_name, qname);
+        }
+        assert_eq!(expected_pos, packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_qname_exceeds_maximum_jumps() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        // Pointer pointing to itself
+        buf[12] = 0xC0;
+        buf[13] = 0x0C;
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(12);
+        let res = packet_buffer.read_qname();
+        let expected_str = "Invalid qname, exceeded maximum number of jumps: max jumps=5";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_qname_exceeds_configured_maximum_jumps() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x03, b'c', b'o', b'm', 0x00, // com
+            0x06, b'g', b'o', b'o', b'g', b'l', b'e', 0xC0, 0x0C, // google + pointer to com
+            0xC0, 0x11, // pointer to google.com
+        ];
+        buf[12..12 + qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        packet_buffer.set_parse_options(ParseOptions {
+            max_jumps: 1,
+            ..ParseOptions::default()
+        });
+        _ = packet_buffer.seek(26);
+        let expected_str = "Invalid qname, exceeded maximum number of jumps: max jumps=1";
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+
+        packet_buffer.set_parse_options(ParseOptions {
+            max_jumps: 2,
+            ..ParseOptions::default()
+        });
+        _ = packet_buffer.seek(26);
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_ok_and(|val| val == "google.com")
+        );
+    }
+
+    fn forward_pointer_buffer() -> PacketBuffer {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x03, b'w', b'w', b'w', 0xC0, 0x12, // www + pointer to later in the packet
+            0x03, b'c', b'o', b'm', 0x00, // com
+        ];
+        buf[12..12 + qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(12);
+        packet_buffer
+    }
+
+    #[test]
+    fn forward_compression_pointer_followed_when_tolerant() {
+        let mut packet_buffer = forward_pointer_buffer();
+        packet_buffer.set_parse_options(ParseOptions {
+            strict_compression: false,
+            ..ParseOptions::default()
+        });
+        assert_eq!(
+            true,
+            packet_buffer.read_qname().is_ok_and(|val| val == "www.com")
+        );
+        assert_eq!(18, packet_buffer.pos());
+    }
+
+    #[test]
+    fn forward_compression_pointer_rejected_when_strict() {
+        let mut packet_buffer = forward_pointer_buffer();
+        packet_buffer.set_parse_options(ParseOptions {
+            strict_compression: true,
+            ..ParseOptions::default()
+        });
+        let expected_str =
+            "Invalid qname, compression pointer doesn't point backwards: pos=16, offset=18";
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_qname_has_extended_label_type() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [0x03, b'w', b'w', b'w', 0x41, 0x08, 0xAB, 0x00];
+        buf[..qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let res = packet_buffer.read_qname();
+        let expected_str = "Invalid qname, unsupported label type: length byte=0x41, pos=4";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_qname_has_reserved_label_type() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        buf[0] = 0x80;
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let res = packet_buffer.read_qname();
+        let expected_str = "Invalid qname, unsupported label type: length byte=0x80, pos=0";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn written_qname_is_read_back_as_same_value() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let qname = "www.google.com";
+        assert_eq!(true, packet_buffer.write_qname(qname).is_ok());
+        assert_eq!(16, packet_buffer.pos());
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            packet_buffer.read_qname().is_ok_and(|val| val == qname)
+        );
+    }
+
+    #[test]
+    fn return_error_if_writing_label_longer_than_maximum_label_length() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let label = "a".repeat(64);
+        let res = packet_buffer.write_qname(&label);
+        let expected_str = format!(
+            "Invalid label, exceeding maximum label length: max length=63, label={}",
+            label
+        );
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn set_u16_value_without_moving_pos() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let val = 0x0305;
+        assert_eq!(true, packet_buffer.set_u16(10, val).is_ok());
+        assert_eq!(0, packet_buffer.pos());
+        _ = packet_buffer.seek(10);
+        assert_eq!(true, packet_buffer.read_u16().is_ok_and(|res| res == val));
+    }
+
+    #[test]
+    fn return_error_if_setting_value_past_end_of_buffer() {
+        let buf = [0; PACKET_BYTES_LENGTH];
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let res = packet_buffer.set(PACKET_BYTES_LENGTH, 1);
+        let expected_str =
+            "Invalid set, setting value past buffer boundary: buffer length=512, pos=512";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    fn google_response_packet() -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        packet.header.id = 0x862a;
+        packet.header.response = true;
+        packet.header.recursion_desired = true;
+        packet.header.recursion_available = true;
+        packet.header.rescode = ResultCode::NoError;
+        packet
+            .questions
+            .push(DnsQuestion::new("google.com".to_string(), QueryType::A));
+        packet.answers.push(DnsRecord::A {
+            domain: "google.com".to_string(),
+            addr: Ipv4Addr::new(216, 58, 211, 142),
+            class: CLASS_IN,
+            ttl: 293,
+        });
+        packet.authorities.push(DnsRecord::NS {
+            domain: "google.com".to_string(),
+            host: "ns1.google.com".to_string(),
+            class: CLASS_IN,
+            ttl: 3600,
+        });
+        packet.resources.push(DnsRecord::A {
+            domain: "ns1.google.com".to_string(),
+            addr: Ipv4Addr::new(216, 239, 32, 10),
+            class: CLASS_IN,
+            ttl: 3600,
+        });
+        packet
+    }
+
+    #[test]
+    fn packet_survives_round_trip_with_header_counts_from_sections() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(1, res.header.questions);
+        assert_eq!(1, res.header.answers);
+        assert_eq!(1, res.header.authoritative_entries);
+        assert_eq!(1, res.header.resource_entries);
+        assert_eq!(packet.questions, res.questions);
+        assert_eq!(packet.answers, res.answers);
+        assert_eq!(packet.authorities, res.authorities);
+        assert_eq!(packet.resources, res.resources);
+    }
+
+    #[test]
+    fn reverse_query_for_ipv4_address() {
+        let packet = DnsPacket::new_reverse_query("192.0.2.1".parse().unwrap(), &mut RandomTxIds);
+        let expected_question =
+            DnsQuestion::new("1.2.0.192.in-addr.arpa".to_string(), QueryType::PTR);
+        assert_eq!(vec![expected_question], packet.questions);
+        assert_eq!(true, packet.header.recursion_desired);
+        assert_eq!(false, packet.header.response);
+    }
+
+    #[test]
+    fn reverse_query_with_sequential_ids_has_exact_bytes() {
+        let mut ids = SequentialTxIds::starting_at(0x1234);
+        let packet = DnsPacket::new_reverse_query("192.0.2.1".parse().unwrap(), &mut ids);
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let expected_bytes = [
+            0x12, 0x34, // id
+            0x01, 0x00, // flags with RD set
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // counts
+            0x01, b'1', 0x01, b'2', 0x01, b'0', 0x03, b'1', b'9', b'2', // 1.2.0.192
+            0x07, b'i', b'n', b'-', b'a', b'd', b'd', b'r', 0x04, b'a', b'r', b'p', b'a', 0x00,
+            0x00, 0x0C, // qtype
+            0x00, 0x01, // class
+        ];
+        assert_eq!(expected_bytes.len(), packet_buffer.pos());
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, expected_bytes.len())
+                .is_ok_and(|bytes| bytes == expected_bytes)
+        );
+
+        let next_packet = DnsPacket::new_reverse_query("192.0.2.1".parse().unwrap(), &mut ids);
+        assert_eq!(0x1235, next_packet.header.id);
+    }
+
+    #[test]
+    fn reverse_query_for_ipv6_address() {
+        let packet =
+            DnsPacket::new_reverse_query("2001:db8::567:89ab".parse().unwrap(), &mut RandomTxIds);
+        let expected_name =
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa";
+        let expected_question = DnsQuestion::new(expected_name.to_string(), QueryType::PTR);
+        assert_eq!(vec![expected_question], packet.questions);
+    }
+
+    #[test]
+    fn strict_parse_accepts_packet_without_trailing_bytes() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos();
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_strict(&mut packet_buffer, len).is_ok()
+        );
+    }
+
+    #[test]
+    fn strict_parse_accepts_trailing_zero_padding() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos() + 4;
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_strict(&mut packet_buffer, len).is_ok()
+        );
+    }
+
+    #[test]
+    fn strict_parse_rejects_trailing_non_zero_bytes() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let records_end = packet_buffer.pos();
+        _ = packet_buffer.write(0xAB);
+        let len = packet_buffer.pos();
+        _ = packet_buffer.seek(0);
+        let expected_str = format!(
+            "Invalid packet, trailing bytes after last record: received length={}, pos={}",
+            len, records_end
+        );
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_strict(&mut packet_buffer, len)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+        _ = packet_buffer.seek(0);
+        assert_eq!(true, DnsPacket::from_buffer(&mut packet_buffer).is_ok());
+    }
+
+    #[test]
+    fn strict_parse_rejects_records_past_received_length() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let records_end = packet_buffer.pos();
+        _ = packet_buffer.seek(0);
+        let expected_str = format!(
+            "Invalid packet, records extend past received length: received length={}, pos={}",
+            records_end - 1,
+            records_end
+        );
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_strict(&mut packet_buffer, records_end - 1)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_header_counts_claim_more_records_than_can_fit() {
+        let mut header = DnsHeader::new();
+        header.answers = 50;
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        header.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        // 50 answers need at least 550 bytes, but only 500 remain after the header
+        let expected_str = "Invalid packet, header counts claim more entries than can fit in remaining bytes: questions=0, answers=50, authorities=0, resources=0, remaining=500";
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn parse_options_passed_to_packet_parsing_apply_to_names() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let packet_bytes = [
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // header
+            0xC0, 0x12, // pointer to the name following the question fields
+            0x00, 0x01, 0x00, 0x01, // qtype, class
+            0x03, b'c', b'o', b'm', 0x00, // com
+        ];
+        buf[..packet_bytes.len()].copy_from_slice(&packet_bytes);
+
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let tolerant = ParseOptions {
+            strict_compression: false,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_with_options(&mut packet_buffer, tolerant)
+                .is_ok_and(|packet| packet.questions[0].name == "com")
+        );
+
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let strict = ParseOptions {
+            strict_compression: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_with_options(&mut packet_buffer, strict).is_err()
+        );
+    }
+
+    #[test]
+    fn buffer_parse_options_restored_after_parsing_with_options() {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        DnsPacket::new().write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let buffer_options = ParseOptions {
+            max_jumps: 2,
+            ..ParseOptions::default()
+        };
+        packet_buffer.set_parse_options(buffer_options);
+        let strict = ParseOptions {
+            strict_compression: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_with_options(&mut packet_buffer, strict).is_ok()
+        );
+        assert_eq!(buffer_options, packet_buffer.parse_options());
+    }
+
+    #[test]
+    fn duplicate_records_within_section_collapsed_preserving_order() {
+        let a_record = |octet, ttl| DnsRecord::A {
+            domain: "google.com".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, octet),
+            class: CLASS_IN,
+            ttl,
+        };
+        let mut packet = DnsPacket::new();
+        packet.answers = vec![
+            a_record(1, 300),
+            a_record(2, 300),
+            a_record(1, 300),
+            a_record(1, 60),
+            a_record(3, 300),
+            a_record(2, 300),
+        ];
+        packet.dedup_records();
+        assert_eq!(
+            vec![a_record(1, 300), a_record(2, 300), a_record(3, 300)],
+            packet.answers
+        );
+    }
+
+    #[test]
+    fn same_record_in_different_sections_not_deduplicated() {
+        let mut packet = google_response_packet();
+        let record = packet.answers[0].clone();
+        packet.resources.push(record.clone());
+        packet.dedup_records();
+        assert_eq!(vec![record.clone()], packet.answers);
+        assert_eq!(2, packet.resources.len());
+        assert_eq!(record, packet.resources[1]);
+    }
+
+    #[test]
+    fn min_ttl_taken_across_answers_and_authorities() {
+        let mut packet = google_response_packet();
+        packet.answers.push(DnsRecord::A {
+            domain: "google.com".to_string(),
+            addr: Ipv4Addr::new(216, 58, 211, 143),
+            class: CLASS_IN,
+            ttl: 600,
+        });
+        packet.authorities.push(DnsRecord::NS {
+            domain: "google.com".to_string(),
+            host: "ns1.google.com".to_string(),
+            class: CLASS_IN,
+            ttl: 45,
+        });
+        packet.resources.push(DnsRecord::A {
+            domain: "ns1.google.com".to_string(),
+            addr: Ipv4Addr::new(216, 239, 32, 10),
+            class: CLASS_IN,
+            ttl: 5,
+        });
+        assert_eq!(Some(45), packet.min_ttl());
+    }
+
+    #[test]
+    fn min_ttl_is_none_without_records() {
+        assert_eq!(None, DnsPacket::new().min_ttl());
+    }
+
+    #[test]
+    fn tolerant_parse_keeps_answers_present_when_header_claims_more() {
+        let mut packet = google_response_packet();
+        packet.authorities.clear();
+        packet.resources.clear();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos();
+        _ = packet_buffer.set_u16(6, 3);
+        _ = packet_buffer.seek(0);
+
+        let res = DnsPacket::from_buffer_tolerant(&mut packet_buffer, len).unwrap();
+        assert_eq!(3, res.header.answers);
+        assert_eq!(packet.questions, res.questions);
+        assert_eq!(packet.answers, res.answers);
+        assert_eq!(true, res.truncated_parse);
+    }
+
+    #[test]
+    fn tolerant_parse_stops_at_record_cut_off_partway() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        // Cut the packet off inside the address of the additional record
+        let len = packet_buffer.pos() - 2;
+        _ = packet_buffer.seek(0);
+
+        let res = DnsPacket::from_buffer_tolerant(&mut packet_buffer, len).unwrap();
+        assert_eq!(packet.answers, res.answers);
+        assert_eq!(packet.authorities, res.authorities);
+        assert_eq!(true, res.resources.is_empty());
+        assert_eq!(true, res.truncated_parse);
+    }
+
+    #[test]
+    fn return_error_if_tolerant_parse_meets_malformed_name_within_len() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let packet_bytes = [
+            0x12, 0x34, 0x01, 0x00, // id, flags
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // counts
+            0x03, b'w', b'w', b'w', 0x41, 0x00, // www + extended label type
+            0x00, 0x01, 0x00, 0x01, // qtype, class
+        ];
+        buf[..packet_bytes.len()].copy_from_slice(&packet_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str = "Invalid qname, unsupported label type: length byte=0x41, pos=16";
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer_tolerant(&mut packet_buffer, packet_bytes.len())
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn tolerant_parse_of_complete_packet_not_flagged() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer_tolerant(&mut packet_buffer, len).unwrap();
+        assert_eq!(packet.answers, res.answers);
+        assert_eq!(packet.resources, res.resources);
+        assert_eq!(false, res.truncated_parse);
+    }
+
+    #[test]
+    fn concatenated_messages_parsed_using_consumed_length() {
+        let first = google_response_packet();
+        let mut second = DnsPacket::new();
+        second.header.id = 0x1234;
+        second
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::AAAA));
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        first.write(&mut packet_buffer).unwrap();
+        let first_len = packet_buffer.pos();
+        second.write(&mut packet_buffer).unwrap();
+        let total_len = packet_buffer.pos();
+        _ = packet_buffer.seek(0);
+
+        let (res, consumed) = DnsPacket::from_buffer_consumed(&mut packet_buffer).unwrap();
+        assert_eq!(first.answers, res.answers);
+        assert_eq!(first.resources, res.resources);
+        assert_eq!(first_len, consumed);
+        let (res, consumed) = DnsPacket::from_buffer_consumed(&mut packet_buffer).unwrap();
+        assert_eq!(second.header.id, res.header.id);
+        assert_eq!(second.questions, res.questions);
+        assert_eq!(total_len - first_len, consumed);
+    }
+
+    #[test]
+    fn compression_pointers_in_later_message_relative_to_its_start() {
+        let first = google_response_packet();
+        let mut second = DnsPacket::new();
+        second.header.id = 0x1234;
+        second.header.response = true;
+        second
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::A));
+        // Written as a pointer back to the question name
+        second.answers.push(DnsRecord::A {
+            domain: "example.com".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 1),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let mut total_len = 0;
+        for packet in [&first, &second] {
+            let mut message_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+            packet.write_compressed(&mut message_buffer).unwrap();
+            let message = message_buffer.get_range(0, message_buffer.pos()).unwrap();
+            buf[total_len..total_len + message.len()].copy_from_slice(message);
+            total_len += message.len();
+        }
+        let mut packet_buffer = PacketBuffer::new(buf);
+
+        let (_, first_len) = DnsPacket::from_buffer_consumed(&mut packet_buffer).unwrap();
+        let (res, consumed) = DnsPacket::from_buffer_consumed(&mut packet_buffer).unwrap();
+        assert_eq!(second.header.id, res.header.id);
+        assert_eq!(second.questions, res.questions);
+        assert_eq!(second.answers, res.answers);
+        assert_eq!(total_len - first_len, consumed);
+        assert_eq!(total_len, packet_buffer.pos());
+    }
+
+    #[test]
+    fn incoming_query_forwarded_with_new_id() {
+        let mut incoming = DnsPacket::new();
+        incoming.header.id = 0x0001;
+        incoming.header.checking_disabled = true;
+        incoming
+            .questions
+            .push(DnsQuestion::new("google.com".to_string(), QueryType::A));
+        let opt = DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: Vec::new(),
+        };
+        incoming.authorities = google_response_packet().authorities;
+        incoming.resources = vec![google_response_packet().resources[0].clone(), opt.clone()];
+
+        let mut ids = SequentialTxIds::starting_at(0x4242);
+        let query = incoming.to_forwarded_query(&mut ids);
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        query.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer_strict(&mut packet_buffer, len).unwrap();
+
+        assert_eq!(0x4242, res.header.id);
+        assert_eq!(false, res.header.response);
+        assert_eq!(true, res.header.recursion_desired);
+        assert_eq!(true, res.header.checking_disabled);
+        assert_eq!(incoming.questions, res.questions);
+        assert_eq!(true, res.answers.is_empty());
+        assert_eq!(true, res.authorities.is_empty());
+        assert_eq!(vec![opt], res.resources);
+    }
+
+    #[test]
+    fn forwarded_response_clears_ad_and_echoes_client_cd() {
+        let mut incoming = DnsPacket::new();
+        incoming.header.id = 0x0001;
+        incoming.header.checking_disabled = true;
+        incoming
+            .questions
+            .push(DnsQuestion::new("google.com".to_string(), QueryType::A));
+        let mut upstream_response = google_response_packet();
+        upstream_response.header.id = 0x4242;
+        upstream_response.header.authed_data = true;
+        upstream_response.header.checking_disabled = false;
+
+        let response = upstream_response.to_forwarded_response(&incoming);
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+
+        assert_eq!(0x0001, res.header.id);
+        assert_eq!(false, res.header.authed_data);
+        assert_eq!(true, res.header.checking_disabled);
+        assert_eq!(upstream_response.answers, res.answers);
+    }
+
+    #[test]
+    fn ad_and_cd_bits_survive_packet_round_trip() {
+        let mut packet = google_response_packet();
+        packet.header.authed_data = true;
+        packet.header.checking_disabled = true;
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(true, res.header.authed_data);
+        assert_eq!(true, res.header.checking_disabled);
+    }
+
+    #[test]
+    fn query_for_refused_qtype_gets_refused_response() {
+        let mut query = DnsPacket::new();
+        query.header.id = 0x0001;
+        query.header.recursion_desired = true;
+        query
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::ANY));
+        let refused_qtypes = HashSet::from([QueryType::ANY]);
+
+        let res = query.refused_response(&refused_qtypes).unwrap();
+        assert_eq!(0x0001, res.header.id);
+        assert_eq!(true, res.header.response);
+        assert_eq!(true, res.header.recursion_desired);
+        assert_eq!(ResultCode::Refused, res.header.rescode);
+        assert_eq!(query.questions, res.questions);
+        assert_eq!(true, res.answers.is_empty());
+    }
+
+    #[test]
+    fn query_for_other_qtype_not_refused() {
+        let mut query = DnsPacket::new();
+        query
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::A));
+        let refused_qtypes = HashSet::from([QueryType::ANY]);
+        assert_eq!(None, query.refused_response(&refused_qtypes));
+    }
+
+    #[test]
+    fn mx_answer_gets_mail_host_address_in_additional_section() {
+        let mut response = DnsPacket::new();
+        response.header.response = true;
+        response
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::MX));
+        response.answers.push(DnsRecord::MX {
+            domain: "example.com".to_string(),
+            priority: 10,
+            host: "mail.example.com".to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        let opt = DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: Vec::new(),
+        };
+        response.resources.push(opt.clone());
+        let mail_addr = DnsRecord::A {
+            domain: "mail.example.com".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 25),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        let known = [
+            DnsRecord::A {
+                domain: "www.example.com".to_string(),
+                addr: Ipv4Addr::new(192, 0, 2, 80),
+                class: CLASS_IN,
+                ttl: 300,
+            },
+            mail_addr.clone(),
+            DnsRecord::MX {
+                domain: "mail.example.com".to_string(),
+                priority: 10,
+                host: "backup.example.com".to_string(),
+                class: CLASS_IN,
+                ttl: 300,
+            },
+        ];
+
+        response.add_additional_addresses(&known);
+        response.add_additional_addresses(&known);
+        assert_eq!(vec![mail_addr, opt], response.resources);
+    }
+
+    #[test]
+    fn srv_answer_gets_target_address_in_additional_section() {
+        let mut response = DnsPacket::new();
+        response.answers.push(DnsRecord::SRV {
+            domain: "_sip._tcp.example.com".to_string(),
+            priority: 10,
+            weight: 60,
+            port: 5060,
+            target: "sip.example.com".to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        let target_addr = DnsRecord::AAAA {
+            domain: "SIP.example.com".to_string(),
+            addr: "2001:db8::5060".parse().unwrap(),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        response.add_additional_addresses(std::slice::from_ref(&target_addr));
+        assert_eq!(vec![target_addr], response.resources);
+    }
+
+    #[test]
+    fn return_error_if_compression_pointer_targets_header() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        // Header bytes that would read as the label "ab" if the pointer were followed
+        buf[4..8].copy_from_slice(&[0x02, b'a', b'b', 0x00]);
+        buf[12..18].copy_from_slice(&[0x03, b'w', b'w', b'w', 0xC0, 0x04]);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(12);
+        let res = packet_buffer.read_qname();
+        let expected_str =
+            "Invalid qname, compression pointer points into the header: pos=16, offset=4";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    fn written_bytes(packet: &DnsPacket, compressed: bool) -> Vec<u8> {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        if compressed {
+            packet.write_compressed(&mut packet_buffer).unwrap();
+        } else {
+            packet.write(&mut packet_buffer).unwrap();
+        }
+        packet_buffer
+            .get_range(0, packet_buffer.pos())
+            .unwrap()
+            .to_vec()
+    }
+
+    fn parse_bytes(bytes: &[u8]) -> DnsPacket {
+        let mut packet_buffer = PacketBuffer::from_reader(&mut &bytes[..], bytes.len()).unwrap();
+        packet_buffer.set_parse_options(ParseOptions {
+            strict_compression: true,
+            ..ParseOptions::default()
+        });
+        DnsPacket::from_buffer_strict(&mut packet_buffer, bytes.len()).unwrap()
+    }
+
+    #[test]
+    fn compressed_write_parses_to_same_packet_as_uncompressed() {
+        let packet = google_response_packet();
+        let uncompressed = written_bytes(&packet, false);
+        let compressed = written_bytes(&packet, true);
+        assert_eq!(parse_bytes(&uncompressed), parse_bytes(&compressed));
+        // google.com is written in full once, in the question. The answer and NS owner names
+        // point to it (saving 10 bytes each), as does the NS data after its ns1 label (saving
+        // 10), and the glue owner points to that NS data (saving 14)
+        assert_eq!(uncompressed.len() - 44, compressed.len());
+    }
+
+    #[test]
+    fn recurring_name_compressed_to_pointer_at_first_occurrence() {
+        let packet = google_response_packet();
+        let compressed = written_bytes(&packet, true);
+        let answer_pos = HEADER_LENGTH + 12 + 4;
+        assert_eq!([0xC0, 0x0C], compressed[answer_pos..answer_pos + 2]);
+    }
+
+    #[test]
+    fn compressed_write_of_unique_names_matches_uncompressed() {
+        let mut packet = DnsPacket::new();
+        packet
+            .questions
+            .push(DnsQuestion::new("google.com".to_string(), QueryType::A));
+        packet.answers.push(DnsRecord::A {
+            domain: "example.org".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 1),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        assert_eq!(written_bytes(&packet, false), written_bytes(&packet, true));
+    }
+
+    #[test]
+    fn bytes_written_at_current_position_and_read_back() {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        _ = packet_buffer.write(0xFF);
+        let bytes = [0x12, 0x34, 0x56, 0x78, 0x9a];
+        assert_eq!(true, packet_buffer.write_bytes(&bytes).is_ok());
+        assert_eq!(1 + bytes.len(), packet_buffer.pos());
+        _ = packet_buffer.seek(1);
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_bytes(bytes.len())
+                .is_ok_and(|val| val == bytes)
+        );
+    }
+
+    #[test]
+    fn return_error_if_writing_bytes_past_buffer_boundary() {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        _ = packet_buffer.seek(510);
+        let expected_str =
+            "Invalid write, writing bytes past buffer boundary: buffer length=512, pos=510, len=3";
+        assert_eq!(
+            true,
+            packet_buffer
+                .write_bytes(&[0x01, 0x02, 0x03])
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+        assert_eq!(510, packet_buffer.pos());
+    }
+
+    #[test]
+    fn written_packet_is_plausible() {
+        let packet = google_response_packet();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos();
+        let datagram = packet_buffer.get_range(0, len).unwrap();
+        assert_eq!(true, DnsPacket::is_plausible(datagram));
+    }
+
+    #[test]
+    fn datagram_shorter_than_header_not_plausible() {
+        assert_eq!(false, DnsPacket::is_plausible(&[0x12, 0x34, 0x01, 0x00]));
+    }
+
+    #[test]
+    fn datagram_with_unassigned_opcode_not_plausible() {
+        let datagram = [
+            0x12, 0x34, // id
+            0x18, 0x00, // flags with opcode 3
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // counts
+        ];
+        assert_eq!(false, DnsPacket::is_plausible(&datagram));
+    }
+
+    #[test]
+    fn http_request_not_plausible() {
+        let datagram = b"GET / HTTP/1.1\r\nHost: 192.0.2.1\r\n\r\n";
+        assert_eq!(false, DnsPacket::is_plausible(datagram));
+    }
+
+    fn binary_label_buffer(label_policy: LabelPolicy) -> PacketBuffer {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x04, b'c', 0xFF, b'f', b'e', // label with a non-ASCII byte
+            0x03, b'c', b'o', b'm', 0x00, // com
+        ];
+        buf[12..12 + qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        packet_buffer.set_parse_options(ParseOptions {
+            label_policy,
+            ..ParseOptions::default()
+        });
+        _ = packet_buffer.seek(12);
+        packet_buffer
+    }
+
+    #[test]
+    fn non_ascii_label_byte_rejected_under_reject_policy() {
+        let mut packet_buffer = binary_label_buffer(LabelPolicy::Reject);
+        let expected_str = "Invalid qname, label contains non-ASCII byte: byte=0xff, pos=13";
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_err_and(|err_str| err_str == expected_str)
         );
-        assert_eq!(4, packet_buffer.pos());
+    }
+
+    #[test]
+    fn non_ascii_label_byte_escaped_under_escape_policy() {
+        let mut packet_buffer = binary_label_buffer(LabelPolicy::Escape);
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_ok_and(|val| val == "c\\255fe.com")
+        );
+    }
+
+    #[test]
+    fn non_ascii_label_byte_replaced_under_lossy_policy() {
+        let mut packet_buffer = binary_label_buffer(LabelPolicy::Lossy);
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_ok_and(|val| val == "c\u{FFFD}fe.com")
+        );
+    }
+
+    #[test]
+    fn labels_escaped_by_default_like_dig() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x05, b'a', b'.', b'b', b' ', b'\\', // label with a dot, space and backslash
+            0x03, b'c', b'o', b'm', 0x00, // com
+        ];
+        buf[12..12 + qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(12);
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_ok_and(|val| val == "a\\.b\\032\\\\.com")
+        );
+    }
+
+    #[test]
+    fn escaped_label_bytes_written_back_as_read() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let qname_bytes = [
+            0x03, b'a', b'.', 0xFF, // label with a dot and a non-ASCII byte
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x00, // example
+        ];
+        buf[..qname_bytes.len()].copy_from_slice(&qname_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let qname = packet_buffer.read_qname().unwrap();
+        assert_eq!("a\\.\\255.example", qname);
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet_buffer.write_qname(&qname).unwrap();
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, packet_buffer.pos())
+                .is_ok_and(|val| val == qname_bytes)
+        );
+    }
+
+    #[test]
+    fn escaped_names_compressed_by_label() {
+        let mut packet = DnsPacket::new();
+        packet
+            .questions
+            .push(DnsQuestion::new("a\\.b.example".to_string(), QueryType::A));
+        packet.answers.push(DnsRecord::A {
+            domain: "a\\.b.example".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 1),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write_compressed(&mut packet_buffer).unwrap();
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(12, 13)
+                .is_ok_and(|val| val == b"\x03a.b\x07example\x00")
+        );
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer(&mut packet_buffer)
+                .is_ok_and(|res| res.questions == packet.questions && res.answers == packet.answers)
+        );
+    }
+
+    #[test]
+    fn return_error_if_writing_label_with_escaped_byte_out_of_range() {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        let expected_str = "Invalid label, escaped byte out of range: label=a\\256, byte=256";
+        assert_eq!(
+            true,
+            packet_buffer
+                .write_qname("a\\256.com")
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn packets_differing_only_by_id_semantically_equal() {
+        let packet = google_response_packet();
+        let mut other = google_response_packet();
+        other.header.id = 0x1234;
+        assert_eq!(false, packet == other);
+        assert_eq!(true, packet.semantically_equal(&other));
+    }
+
+    #[test]
+    fn packets_differing_by_answer_not_semantically_equal() {
+        let packet = google_response_packet();
+        let mut other = google_response_packet();
+        if let DnsRecord::A { ref mut ttl, .. } = other.answers[0] {
+            *ttl += 1;
+        }
+        assert_eq!(false, packet.semantically_equal(&other));
+    }
+
+    #[test]
+    fn equal_packets_hash_equally() {
+        let mut packets = HashSet::new();
+        packets.insert(google_response_packet());
+        assert_eq!(true, packets.contains(&google_response_packet()));
+
+        let mut other = google_response_packet();
+        other.header.id = 0x1234;
+        assert_eq!(false, packets.contains(&other));
+    }
+
+    #[test]
+    fn packet_presented_by_section() {
+        let expected = "\
+;; QUESTION SECTION:
+;google.com. IN A
+
+;; ANSWER SECTION:
+google.com. 293 IN A 216.58.211.142
+
+;; AUTHORITY SECTION:
+google.com. 3600 IN NS ns1.google.com.
+
+;; ADDITIONAL SECTION:
+ns1.google.com. 3600 IN A 216.239.32.10";
+        assert_eq!(expected, google_response_packet().to_presentation());
+    }
+
+    /// Buffer holding a name at offset 12 made of a label of `first_len` bytes, followed by
+    /// `chained` names that each add a 63 byte label in front of the previous one through a
+    /// pointer. Returns the buffer positioned at the last name.
+    fn chained_long_labels(first_len: usize, chained: usize) -> PacketBuffer {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let mut pos = 12;
+        buf[pos] = first_len as u8;
+        buf[pos + 1..pos + 1 + first_len].fill(b'a');
+        let mut prev = pos;
+        pos += first_len + 2;
+        for _ in 0..chained {
+            buf[pos] = 63;
+            buf[pos + 1..pos + 64].fill(b'b');
+            buf[pos + 64] = 0xC0;
+            buf[pos + 65] = prev as u8;
+            prev = pos;
+            pos += 66;
+        }
+        let mut packet_buffer = PacketBuffer::new(buf);
+        _ = packet_buffer.seek(prev);
+        packet_buffer
+    }
+
+    #[test]
+    fn pointer_chain_decoding_to_longest_name_accepted() {
+        // Three 63 byte labels in front of a 61 byte label is exactly 255 bytes
+        let mut packet_buffer = chained_long_labels(61, 3);
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_ok_and(|val| val.len() == 3 * 64 + 61)
+        );
+    }
+
+    #[test]
+    fn return_error_if_pointer_chain_decodes_to_over_long_name() {
+        let mut packet_buffer = chained_long_labels(63, 4);
+        let expected_str = "Invalid qname, name exceeds maximum length: max length=255, pos=77";
+        assert_eq!(
+            true,
+            packet_buffer
+                .read_qname()
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn forwarded_query_keeps_question_class() {
+        let mut incoming = DnsPacket::new();
+        let mut question = DnsQuestion::new("version.bind".to_string(), QueryType::TXT);
+        question.class = CLASS_CH;
+        incoming.questions.push(question);
+
+        let mut ids = SequentialTxIds::starting_at(0x4242);
+        let query = incoming.to_forwarded_query(&mut ids);
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        query.write(&mut packet_buffer).unwrap();
+        let len = packet_buffer.pos();
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(len - 2, 2)
+                .is_ok_and(|val| val == [0x00, 0x03])
+        );
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer_strict(&mut packet_buffer, len).unwrap();
+        assert_eq!(incoming.questions, res.questions);
+        assert_eq!(
+            true,
+            res.to_presentation()
+                .lines()
+                .any(|line| line == ";version.bind. CH TXT")
+        );
+    }
+
+    fn many_answers_response() -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        packet.header.id = 0x1234;
+        packet.header.response = true;
+        packet
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::A));
+        for octet in 1..=12 {
+            packet.answers.push(DnsRecord::A {
+                domain: "example.com".to_string(),
+                addr: Ipv4Addr::new(192, 0, 2, octet),
+                class: CLASS_IN,
+                ttl: 300,
+            });
+        }
+        packet.resources.push(DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: Vec::new(),
+        });
+        packet
+    }
+
+    #[test]
+    fn response_over_max_length_truncated_to_fit() {
+        let response = many_answers_response();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write(&mut packet_buffer).unwrap();
+        assert_eq!(true, packet_buffer.pos() > 200);
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write_truncated(&mut packet_buffer, 200).unwrap();
+        let len = packet_buffer.pos();
+        assert_eq!(true, len <= 200);
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer_strict(&mut packet_buffer, len).unwrap();
+
+        assert_eq!(true, res.header.truncated_message);
+        assert_eq!(response.questions, res.questions);
+        // 40 bytes of header, question and OPT leave room for five 27 byte answers
+        assert_eq!(response.answers[..5], res.answers[..]);
+        assert_eq!(response.resources, res.resources);
+    }
+
+    #[test]
+    fn response_within_max_length_written_whole() {
+        let response = many_answers_response();
+        let mut whole_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write(&mut whole_buffer).unwrap();
+        let len = whole_buffer.pos();
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write_truncated(&mut packet_buffer, len).unwrap();
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, packet_buffer.pos())
+                .is_ok_and(|val| val == whole_buffer.get_range(0, len).unwrap())
+        );
+    }
+
+    #[test]
+    fn return_error_if_question_does_not_fit_in_max_length() {
+        let response = many_answers_response();
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        let res = response.write_truncated(&mut packet_buffer, 20);
+        let expected_str =
+            "Invalid max length, question and OPT record don't fit: max length=20, len=40";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    fn blocked_query(qtype: QueryType) -> DnsPacket {
+        let mut query = DnsPacket::new();
+        query.header.id = 0x2a2a;
+        query.header.recursion_desired = true;
+        query
+            .questions
+            .push(DnsQuestion::new("ads.example.com".to_string(), qtype));
+        query
+    }
+
+    #[test]
+    fn blocked_query_answered_with_nxdomain() {
+        let query = blocked_query(QueryType::A);
+        let response = query.blocked_response(BlockResponse::NxDomain);
+        assert_eq!(0x2a2a, response.header.id);
+        assert_eq!(true, response.header.response);
+        assert_eq!(true, response.header.recursion_desired);
+        assert_eq!(ResultCode::NxDomain, response.header.rescode);
+        assert_eq!(query.questions, response.questions);
+        assert_eq!(true, response.answers.is_empty());
+    }
+
+    #[test]
+    fn blocked_query_answered_with_nodata() {
+        let query = blocked_query(QueryType::A);
+        let response = query.blocked_response(BlockResponse::NoData);
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(query.questions, response.questions);
+        assert_eq!(true, response.answers.is_empty());
+    }
+
+    #[test]
+    fn blocked_query_answered_with_refused() {
+        let query = blocked_query(QueryType::A);
+        let response = query.blocked_response(BlockResponse::Refused);
+        assert_eq!(ResultCode::Refused, response.header.rescode);
+        assert_eq!(query.questions, response.questions);
+        assert_eq!(true, response.answers.is_empty());
+    }
+
+    #[test]
+    fn blocked_query_answered_with_sinkhole_address() {
+        let query = blocked_query(QueryType::A);
+        let sinkhole = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let response = query.blocked_response(BlockResponse::Sinkhole(sinkhole));
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(
+            vec![DnsRecord::A {
+                domain: "ads.example.com".to_string(),
+                addr: Ipv4Addr::UNSPECIFIED,
+                class: CLASS_IN,
+                ttl: SINKHOLE_TTL,
+            }],
+            response.answers
+        );
+    }
+
+    #[test]
+    fn blocked_query_for_other_family_than_sinkhole_answered_with_nodata() {
+        let query = blocked_query(QueryType::AAAA);
+        let sinkhole = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let response = query.blocked_response(BlockResponse::Sinkhole(sinkhole));
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(true, response.answers.is_empty());
+    }
+
+    #[test]
+    fn blocked_response_without_edns_has_no_opt_record() {
+        let query = blocked_query(QueryType::A);
+        let response = query.blocked_response(BlockResponse::NxDomain);
+        assert_eq!(true, response.resources.is_empty());
+    }
+
+    fn notify_query() -> DnsPacket {
+        let mut notify = DnsPacket::new();
+        notify.header.id = 0x5151;
+        notify.header.opcode = OPCODE_NOTIFY;
+        notify.header.authoritative_answer = true;
+        notify
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::SOA));
+        notify
+    }
+
+    #[test]
+    fn notify_acknowledged_and_zone_refreshed() {
+        let notify = notify_query();
+        let mut refreshed = Vec::new();
+        let response = notify
+            .handle_notify(|zone| refreshed.push(zone.to_string()))
+            .unwrap();
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        response.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        let res = DnsPacket::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(0x5151, res.header.id);
+        assert_eq!(true, res.header.response);
+        assert_eq!(OPCODE_NOTIFY, res.header.opcode);
+        assert_eq!(true, res.header.authoritative_answer);
+        assert_eq!(ResultCode::NoError, res.header.rescode);
+        assert_eq!(notify.questions, res.questions);
+        assert_eq!(vec!["example.com".to_string()], refreshed);
+    }
+
+    #[test]
+    fn standard_query_not_handled_as_notify() {
+        let mut query = notify_query();
+        query.header.opcode = 0;
+        let mut refreshed = false;
+        assert_eq!(None, query.handle_notify(|_| refreshed = true));
+        assert_eq!(false, refreshed);
+    }
+
+    #[test]
+    fn notify_response_not_answered_again() {
+        let mut notify = notify_query();
+        notify.header.response = true;
+        assert_eq!(None, notify.notify_response());
+    }
+
+    fn single_question_query(name: &str, qtype: QueryType) -> DnsPacket {
+        let mut query = DnsPacket::new();
+        query.header.id = 0x6761;
+        query.header.recursion_desired = true;
+        query
+            .questions
+            .push(DnsQuestion::new(name.to_string(), qtype));
+        query
+    }
+
+    #[test]
+    fn localhost_a_query_answered_locally() {
+        let query = single_question_query("localhost", QueryType::A);
+        let response = query.special_use_answer().unwrap();
+        assert_eq!(0x6761, response.header.id);
+        assert_eq!(true, response.header.response);
+        assert_eq!(true, response.header.authoritative_answer);
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(query.questions, response.questions);
+        assert_eq!(
+            vec![DnsRecord::A {
+                domain: "localhost".to_string(),
+                addr: Ipv4Addr::LOCALHOST,
+                class: CLASS_IN,
+                ttl: LOCALHOST_TTL,
+            }],
+            response.answers
+        );
+    }
+
+    #[test]
+    fn localhost_aaaa_query_answered_locally() {
+        let query = single_question_query("app.localhost", QueryType::AAAA);
+        let response = query.special_use_answer().unwrap();
+        assert_eq!(
+            vec![DnsRecord::AAAA {
+                domain: "app.localhost".to_string(),
+                addr: Ipv6Addr::LOCALHOST,
+                class: CLASS_IN,
+                ttl: LOCALHOST_TTL,
+            }],
+            response.answers
+        );
+    }
+
+    #[test]
+    fn loopback_reverse_query_answered_locally() {
+        let query = single_question_query("1.0.0.127.in-addr.arpa", QueryType::PTR);
+        let response = query.special_use_answer().unwrap();
+        assert_eq!(
+            vec![DnsRecord::PTR {
+                domain: "1.0.0.127.in-addr.arpa".to_string(),
+                host: "localhost".to_string(),
+                class: CLASS_IN,
+                ttl: LOCALHOST_TTL,
+            }],
+            response.answers
+        );
+    }
+
+    #[test]
+    fn localhost_query_for_other_type_answered_with_nodata() {
+        let query = single_question_query("localhost", QueryType::MX);
+        let response = query.special_use_answer().unwrap();
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(true, response.answers.is_empty());
+    }
+
+    #[test]
+    fn other_names_not_answered_locally() {
+        for name in ["example.com", "notlocalhost", "2.0.0.127.in-addr.arpa"] {
+            let query = single_question_query(name, QueryType::A);
+            assert_eq!(None, query.special_use_answer());
+        }
+    }
+
+    #[test]
+    fn health_probe_answered_with_static_address() {
+        let addr = Ipv4Addr::new(192, 0, 2, 1);
+        let query = single_question_query("Health.Check", QueryType::A);
+        let response = query.health_response("health.check.", addr).unwrap();
+        assert_eq!(0x6761, response.header.id);
+        assert_eq!(true, response.header.response);
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(query.questions, response.questions);
+        assert_eq!(
+            vec![DnsRecord::A {
+                domain: "Health.Check".to_string(),
+                addr,
+                class: CLASS_IN,
+                ttl: HEALTH_TTL,
+            }],
+            response.answers
+        );
+    }
+
+    #[test]
+    fn health_probe_for_other_type_answered_with_nodata() {
+        let query = single_question_query("health.check", QueryType::AAAA);
+        let response = query
+            .health_response("health.check.", Ipv4Addr::new(192, 0, 2, 1))
+            .unwrap();
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(true, response.answers.is_empty());
+    }
+
+    #[test]
+    fn other_names_not_answered_as_health_probe() {
+        for name in ["example.com", "www.health.check"] {
+            let query = single_question_query(name, QueryType::A);
+            assert_eq!(
+                None,
+                query.health_response("health.check.", Ipv4Addr::new(192, 0, 2, 1))
+            );
+        }
+    }
+
+    fn round_robin_response() -> DnsPacket {
+        let mut response = DnsPacket::new();
+        response.answers.push(DnsRecord::CNAME {
+            domain: "www.example.com".to_string(),
+            host: "example.com".to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        for octet in 1..=3 {
+            response.answers.push(DnsRecord::A {
+                domain: "example.com".to_string(),
+                addr: Ipv4Addr::new(192, 0, 2, octet),
+                class: CLASS_IN,
+                ttl: 300,
+            });
+        }
+        response.answers.push(DnsRecord::AAAA {
+            domain: "example.com".to_string(),
+            addr: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        response
+    }
+
+    fn answer_addrs(response: &DnsPacket) -> Vec<Ipv4Addr> {
+        response
+            .answers
+            .iter()
+            .filter_map(|record| match *record {
+                DnsRecord::A { addr, .. } => Some(addr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn consecutive_rotations_cycle_through_all_addresses() {
+        let original = round_robin_response();
+        let first = Ipv4Addr::new(192, 0, 2, 1);
+        let second = Ipv4Addr::new(192, 0, 2, 2);
+        let third = Ipv4Addr::new(192, 0, 2, 3);
+        let expected_orders = [
+            vec![first, second, third],
+            vec![second, third, first],
+            vec![third, first, second],
+            vec![first, second, third],
+        ];
+        for (n, expected_order) in expected_orders.into_iter().enumerate() {
+            let mut response = original.clone();
+            response.rotate_answers(n);
+            assert_eq!(expected_order, answer_addrs(&response));
+        }
+    }
+
+    #[test]
+    fn rotation_keeps_other_answers_in_place() {
+        let original = round_robin_response();
+        let mut response = original.clone();
+        response.rotate_answers(1);
+        assert_eq!(original.answers[0], response.answers[0]);
+        assert_eq!(original.answers[4], response.answers[4]);
+        assert_eq!(original.answers.len(), response.answers.len());
+    }
+
+    #[test]
+    fn cname_chain_ordered_ahead_of_its_target_records() {
+        let cname = |domain: &str, host: &str| DnsRecord::CNAME {
+            domain: domain.to_string(),
+            host: host.to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        let target = DnsRecord::A {
+            domain: "edge.example.net".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 1),
+            class: CLASS_IN,
+            ttl: 20,
+        };
+        let mut response = DnsPacket::new();
+        response.questions.push(DnsQuestion::new(
+            "www.example.com".to_string(),
+            QueryType::A,
+        ));
+        response.answers = vec![
+            target.clone(),
+            cname("cdn.example.com", "edge.example.net"),
+            cname("www.example.com", "cdn.example.com"),
+        ];
+
+        response.order_cname_first();
+        assert_eq!(
+            vec![
+                cname("www.example.com", "cdn.example.com"),
+                cname("cdn.example.com", "edge.example.net"),
+                target,
+            ],
+            response.answers
+        );
+    }
+
+    #[test]
+    fn answers_without_cname_keep_their_order() {
+        let mut response = round_robin_response();
+        response.answers.remove(0);
+        response
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::A));
+        let expected_answers = response.answers.clone();
+        response.order_cname_first();
+        assert_eq!(expected_answers, response.answers);
     }
 }
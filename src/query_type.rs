@@ -0,0 +1,277 @@
+use std::fmt;
+
+/// Every type with a variant of its own, in numeric order
+const KNOWN: &[QueryType] = &[
+    QueryType::A,
+    QueryType::NS,
+    QueryType::CNAME,
+    QueryType::SOA,
+    QueryType::PTR,
+    QueryType::MX,
+    QueryType::TXT,
+    QueryType::AAAA,
+    QueryType::LOC,
+    QueryType::SRV,
+    QueryType::NAPTR,
+    QueryType::OPT,
+    QueryType::APL,
+    QueryType::DS,
+    QueryType::RRSIG,
+    QueryType::NSEC,
+    QueryType::DNSKEY,
+    QueryType::NSEC3,
+    QueryType::SVCB,
+    QueryType::HTTPS,
+    QueryType::TSIG,
+    QueryType::AXFR,
+    QueryType::ANY,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryType {
+    Unknown(u16),
+    A,
+    NS,
+    CNAME,
+    SOA,
+    PTR,
+    MX,
+    TXT,
+    AAAA,
+    LOC,
+    SRV,
+    NAPTR,
+    OPT,
+    APL,
+    DS,
+    RRSIG,
+    NSEC,
+    DNSKEY,
+    NSEC3,
+    SVCB,
+    HTTPS,
+    TSIG,
+    AXFR,
+    ANY,
+}
+
+impl QueryType {
+    pub fn from_num(num: u16) -> QueryType {
+        match num {
+            1 => QueryType::A,
+            2 => QueryType::NS,
+            5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            12 => QueryType::PTR,
+            15 => QueryType::MX,
+            16 => QueryType::TXT,
+            28 => QueryType::AAAA,
+            29 => QueryType::LOC,
+            33 => QueryType::SRV,
+            35 => QueryType::NAPTR,
+            41 => QueryType::OPT,
+            42 => QueryType::APL,
+            43 => QueryType::DS,
+            46 => QueryType::RRSIG,
+            47 => QueryType::NSEC,
+            48 => QueryType::DNSKEY,
+            50 => QueryType::NSEC3,
+            64 => QueryType::SVCB,
+            65 => QueryType::HTTPS,
+            250 => QueryType::TSIG,
+            252 => QueryType::AXFR,
+            255 => QueryType::ANY,
+            _ => QueryType::Unknown(num),
+        }
+    }
+
+    /// Whether records of this type exist for DNSSEC validation, so should only be sent to
+    /// clients that set the DO bit
+    pub fn is_dnssec(&self) -> bool {
+        matches!(
+            *self,
+            QueryType::DS
+                | QueryType::RRSIG
+                | QueryType::NSEC
+                | QueryType::DNSKEY
+                | QueryType::NSEC3
+        )
+    }
+
+    /// Every type the parser has a variant for, in numeric order
+    pub fn all_known() -> &'static [QueryType] {
+        KNOWN
+    }
+
+    pub fn is_known(&self) -> bool {
+        !matches!(*self, QueryType::Unknown(_))
+    }
+
+    pub fn to_num(&self) -> u16 {
+        match *self {
+            QueryType::Unknown(num) => num,
+            QueryType::A => 1,
+            QueryType::NS => 2,
+            QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
+            QueryType::MX => 15,
+            QueryType::TXT => 16,
+            QueryType::AAAA => 28,
+            QueryType::LOC => 29,
+            QueryType::SRV => 33,
+            QueryType::NAPTR => 35,
+            QueryType::OPT => 41,
+            QueryType::APL => 42,
+            QueryType::DS => 43,
+            QueryType::RRSIG => 46,
+            QueryType::NSEC => 47,
+            QueryType::DNSKEY => 48,
+            QueryType::NSEC3 => 50,
+            QueryType::SVCB => 64,
+            QueryType::HTTPS => 65,
+            QueryType::TSIG => 250,
+            QueryType::AXFR => 252,
+            QueryType::ANY => 255,
+        }
+    }
+}
+
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryType::Unknown(num) => write!(f, "TYPE{}", num),
+            QueryType::A => write!(f, "A"),
+            QueryType::NS => write!(f, "NS"),
+            QueryType::CNAME => write!(f, "CNAME"),
+            QueryType::SOA => write!(f, "SOA"),
+            QueryType::PTR => write!(f, "PTR"),
+            QueryType::MX => write!(f, "MX"),
+            QueryType::TXT => write!(f, "TXT"),
+            QueryType::AAAA => write!(f, "AAAA"),
+            QueryType::LOC => write!(f, "LOC"),
+            QueryType::SRV => write!(f, "SRV"),
+            QueryType::NAPTR => write!(f, "NAPTR"),
+            QueryType::OPT => write!(f, "OPT"),
+            QueryType::APL => write!(f, "APL"),
+            QueryType::DS => write!(f, "DS"),
+            QueryType::RRSIG => write!(f, "RRSIG"),
+            QueryType::NSEC => write!(f, "NSEC"),
+            QueryType::DNSKEY => write!(f, "DNSKEY"),
+            QueryType::NSEC3 => write!(f, "NSEC3"),
+            QueryType::SVCB => write!(f, "SVCB"),
+            QueryType::HTTPS => write!(f, "HTTPS"),
+            QueryType::TSIG => write!(f, "TSIG"),
+            QueryType::AXFR => write!(f, "AXFR"),
+            QueryType::ANY => write!(f, "ANY"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_query_type_round_trips_through_num() {
+        for qtype in [
+            QueryType::A,
+            QueryType::NS,
+            QueryType::CNAME,
+            QueryType::SOA,
+            QueryType::PTR,
+            QueryType::MX,
+            QueryType::TXT,
+            QueryType::AAAA,
+            QueryType::LOC,
+            QueryType::SRV,
+            QueryType::NAPTR,
+            QueryType::OPT,
+            QueryType::APL,
+            QueryType::DS,
+            QueryType::RRSIG,
+            QueryType::NSEC,
+            QueryType::DNSKEY,
+            QueryType::NSEC3,
+            QueryType::SVCB,
+            QueryType::HTTPS,
+            QueryType::TSIG,
+            QueryType::AXFR,
+            QueryType::ANY,
+        ] {
+            assert_eq!(qtype, QueryType::from_num(qtype.to_num()));
+        }
+    }
+
+    #[test]
+    fn unrecognised_num_is_unknown_query_type() {
+        let qtype = QueryType::from_num(99);
+        assert_eq!(QueryType::Unknown(99), qtype);
+        assert_eq!(99, qtype.to_num());
+    }
+
+    #[test]
+    fn known_query_types_displayed_as_mnemonic() {
+        assert_eq!("A", QueryType::A.to_string());
+        assert_eq!("NS", QueryType::NS.to_string());
+        assert_eq!("CNAME", QueryType::CNAME.to_string());
+        assert_eq!("SOA", QueryType::SOA.to_string());
+        assert_eq!("PTR", QueryType::PTR.to_string());
+        assert_eq!("MX", QueryType::MX.to_string());
+        assert_eq!("TXT", QueryType::TXT.to_string());
+        assert_eq!("AAAA", QueryType::AAAA.to_string());
+        assert_eq!("LOC", QueryType::LOC.to_string());
+        assert_eq!("SRV", QueryType::SRV.to_string());
+        assert_eq!("NAPTR", QueryType::NAPTR.to_string());
+        assert_eq!("OPT", QueryType::OPT.to_string());
+        assert_eq!("APL", QueryType::APL.to_string());
+        assert_eq!("DS", QueryType::DS.to_string());
+        assert_eq!("RRSIG", QueryType::RRSIG.to_string());
+        assert_eq!("NSEC", QueryType::NSEC.to_string());
+        assert_eq!("DNSKEY", QueryType::DNSKEY.to_string());
+        assert_eq!("NSEC3", QueryType::NSEC3.to_string());
+        assert_eq!("SVCB", QueryType::SVCB.to_string());
+        assert_eq!("HTTPS", QueryType::HTTPS.to_string());
+        assert_eq!("TSIG", QueryType::TSIG.to_string());
+        assert_eq!("AXFR", QueryType::AXFR.to_string());
+        assert_eq!("ANY", QueryType::ANY.to_string());
+    }
+
+    #[test]
+    fn unknown_query_type_displayed_as_type_number() {
+        assert_eq!("TYPE65535", QueryType::Unknown(65535).to_string());
+    }
+
+    #[test]
+    fn only_dnssec_query_types_are_dnssec() {
+        assert_eq!(true, QueryType::RRSIG.is_dnssec());
+        assert_eq!(true, QueryType::DNSKEY.is_dnssec());
+        assert_eq!(false, QueryType::A.is_dnssec());
+        assert_eq!(false, QueryType::Unknown(99).is_dnssec());
+    }
+
+    #[test]
+    fn all_known_includes_common_types_and_excludes_unknown() {
+        let known = QueryType::all_known();
+        for qtype in [QueryType::A, QueryType::AAAA, QueryType::MX, QueryType::SOA] {
+            assert_eq!(true, known.contains(&qtype));
+        }
+        assert_eq!(
+            false,
+            known
+                .iter()
+                .any(|qtype| matches!(qtype, QueryType::Unknown(_)))
+        );
+        assert_eq!(true, QueryType::MX.is_known());
+        assert_eq!(false, QueryType::Unknown(99).is_known());
+    }
+
+    #[test]
+    fn all_known_lists_every_number_with_a_variant() {
+        let from_nums: Vec<QueryType> = (0..=u16::MAX)
+            .map(QueryType::from_num)
+            .filter(QueryType::is_known)
+            .collect();
+        assert_eq!(from_nums, QueryType::all_known());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::packet::PacketBuffer;
+use crate::query_type::QueryType;
+use crate::record::CLASS_IN;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: QueryType,
+    pub class: u16,
+}
+
+impl DnsQuestion {
+    pub fn new(name: String, qtype: QueryType) -> DnsQuestion {
+        DnsQuestion {
+            name,
+            qtype,
+            class: CLASS_IN,
+        }
+    }
+
+    pub fn from_buffer(buffer: &mut PacketBuffer) -> Result<DnsQuestion, String> {
+        let name = buffer.read_qname()?;
+        let qtype = QueryType::from_num(buffer.read_u16()?);
+        let class = buffer.read_u16()?;
+        Ok(DnsQuestion { name, qtype, class })
+    }
+
+    pub fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        buffer.write_compressible_qname(&self.name)?;
+        buffer.write_u16(self.qtype.to_num())?;
+        buffer.write_u16(self.class)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::PACKET_BYTES_LENGTH;
+    use crate::record::CLASS_CH;
+
+    #[test]
+    fn parse_question_from_buffer() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let question_bytes = [
+            0x06, b'g', b'o', b'o', b'g', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, // name
+            0x00, 0x01, // qtype
+            0x00, 0x01, // class
+        ];
+        buf[..question_bytes.len()].copy_from_slice(&question_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_question = DnsQuestion::new("google.com".to_string(), QueryType::A);
+        assert_eq!(
+            true,
+            DnsQuestion::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_question)
+        );
+        assert_eq!(question_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn question_survives_round_trip() {
+        let question = DnsQuestion::new("google.com".to_string(), QueryType::MX);
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        question.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            DnsQuestion::from_buffer(&mut packet_buffer).is_ok_and(|val| val == question)
+        );
+    }
+
+    #[test]
+    fn question_class_written_back_as_read() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let question_bytes = [
+            0x07, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x04, b'b', b'i', b'n', b'd',
+            0x00, // name
+            0x00, 0x10, // qtype
+            0x00, 0x03, // class
+        ];
+        buf[..question_bytes.len()].copy_from_slice(&question_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let question = DnsQuestion::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(CLASS_CH, question.class);
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        question.write(&mut packet_buffer).unwrap();
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, question_bytes.len())
+                .is_ok_and(|val| val == question_bytes)
+        );
+    }
+}
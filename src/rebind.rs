@@ -0,0 +1,218 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::header::ResultCode;
+use crate::packet::DnsPacket;
+use crate::record::{in_zone, DnsRecord};
+
+/// Block of addresses sharing their leading `prefix_len` bits with `addr`, such as
+/// 192.168.0.0/16
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpRange {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl IpRange {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<IpRange, String> {
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_prefix_len {
+            let err_str = format!(
+                "Invalid range, prefix longer than address: address={}, prefix={}",
+                addr, prefix_len
+            );
+            return Err(err_str);
+        }
+        Ok(IpRange { addr, prefix_len })
+    }
+
+    /// Whether `addr` is in the range. An IPv4 range never contains an IPv6 address, nor the
+    /// other way round.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Private (RFC 1918, RFC 4193), loopback, link-local and unspecified ranges, which a public
+/// name has no reason to resolve to
+pub fn default_private_ranges() -> Vec<IpRange> {
+    [
+        (IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8),
+        (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8),
+        (IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)), 8),
+        (IpAddr::V4(Ipv4Addr::new(169, 254, 0, 0)), 16),
+        (IpAddr::V4(Ipv4Addr::new(172, 16, 0, 0)), 12),
+        (IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)), 16),
+        (IpAddr::V6(Ipv6Addr::UNSPECIFIED), 128),
+        (IpAddr::V6(Ipv6Addr::LOCALHOST), 128),
+        (IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0)), 7),
+        (IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0)), 10),
+    ]
+    .into_iter()
+    .map(|(addr, prefix_len)| IpRange { addr, prefix_len })
+    .collect()
+}
+
+/// Remove A and AAAA answers with an address in one of `ranges` from a response, as protection
+/// against DNS rebinding. Responses to questions within one of `local_suffixes` are left alone,
+/// as local names are expected to resolve to private addresses. A response left with no
+/// addresses is answered with NODATA.
+pub fn filter_private_answers(
+    response: &mut DnsPacket,
+    local_suffixes: &[&str],
+    ranges: &[IpRange],
+) {
+    let is_local = response.questions.iter().any(|question| {
+        local_suffixes
+            .iter()
+            .any(|suffix| in_zone(&question.name, suffix))
+    });
+    if is_local {
+        return;
+    }
+
+    let is_rejected = |record: &DnsRecord| {
+        let addr = match *record {
+            DnsRecord::A { addr, .. } => IpAddr::V4(addr),
+            DnsRecord::AAAA { addr, .. } => IpAddr::V6(addr),
+            _ => return false,
+        };
+        ranges.iter().any(|range| range.contains(addr))
+    };
+    if !response.answers.iter().any(is_rejected) {
+        return;
+    }
+
+    response.answers.retain(|record| !is_rejected(record));
+    let has_addresses = response
+        .answers
+        .iter()
+        .any(|record| matches!(record, DnsRecord::A { .. } | DnsRecord::AAAA { .. }));
+    if !has_addresses {
+        response.answers.clear();
+        response.header.rescode = ResultCode::NoError;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query_type::QueryType;
+    use crate::question::DnsQuestion;
+    use crate::record::CLASS_IN;
+
+    fn a_response(name: &str, addrs: &[Ipv4Addr]) -> DnsPacket {
+        let mut response = DnsPacket::new();
+        response.header.response = true;
+        response
+            .questions
+            .push(DnsQuestion::new(name.to_string(), QueryType::A));
+        for &addr in addrs {
+            response.answers.push(DnsRecord::A {
+                domain: name.to_string(),
+                addr,
+                class: CLASS_IN,
+                ttl: 300,
+            });
+        }
+        response
+    }
+
+    #[test]
+    fn public_name_resolving_to_private_address_answered_with_nodata() {
+        let mut response = a_response("rebind.example.com", &[Ipv4Addr::new(192, 168, 1, 10)]);
+        filter_private_answers(&mut response, &["home.arpa"], &default_private_ranges());
+        assert_eq!(true, response.answers.is_empty());
+        assert_eq!(ResultCode::NoError, response.header.rescode);
+        assert_eq!(1, response.questions.len());
+    }
+
+    #[test]
+    fn local_name_resolving_to_private_address_kept() {
+        let mut response = a_response("nas.home.arpa", &[Ipv4Addr::new(192, 168, 1, 10)]);
+        let expected_answers = response.answers.clone();
+        filter_private_answers(&mut response, &["home.arpa"], &default_private_ranges());
+        assert_eq!(expected_answers, response.answers);
+    }
+
+    #[test]
+    fn only_private_addresses_removed_from_mixed_answers() {
+        let public_addr = Ipv4Addr::new(93, 184, 216, 34);
+        let mut response = a_response(
+            "www.example.com",
+            &[Ipv4Addr::new(10, 0, 0, 1), public_addr],
+        );
+        filter_private_answers(&mut response, &[], &default_private_ranges());
+        assert_eq!(
+            vec![DnsRecord::A {
+                domain: "www.example.com".to_string(),
+                addr: public_addr,
+                class: CLASS_IN,
+                ttl: 300,
+            }],
+            response.answers
+        );
+    }
+
+    #[test]
+    fn only_configured_ranges_rejected() {
+        let ranges = vec![IpRange::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8).unwrap()];
+        let mut response = a_response("www.example.com", &[Ipv4Addr::new(192, 168, 1, 10)]);
+        let expected_answers = response.answers.clone();
+        filter_private_answers(&mut response, &[], &ranges);
+        assert_eq!(expected_answers, response.answers);
+    }
+
+    #[test]
+    fn link_local_ipv6_answer_removed() {
+        let mut response = DnsPacket::new();
+        response
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::AAAA));
+        response.answers.push(DnsRecord::AAAA {
+            domain: "example.com".to_string(),
+            addr: Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+            class: CLASS_IN,
+            ttl: 300,
+        });
+        filter_private_answers(&mut response, &[], &default_private_ranges());
+        assert_eq!(true, response.answers.is_empty());
+    }
+
+    #[test]
+    fn range_contains_addresses_sharing_its_prefix() {
+        let range = IpRange::new(IpAddr::V4(Ipv4Addr::new(172, 16, 0, 0)), 12).unwrap();
+        assert_eq!(
+            true,
+            range.contains(IpAddr::V4(Ipv4Addr::new(172, 31, 255, 255)))
+        );
+        assert_eq!(
+            false,
+            range.contains(IpAddr::V4(Ipv4Addr::new(172, 32, 0, 0)))
+        );
+        assert_eq!(false, range.contains(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn return_error_if_range_prefix_longer_than_address() {
+        let res = IpRange::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 33);
+        let expected_str = "Invalid range, prefix longer than address: address=10.0.0.0, prefix=33";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+}
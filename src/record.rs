@@ -0,0 +1,2264 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::edns::EdnsOption;
+use crate::packet::{PacketBuffer, PACKET_BYTES_LENGTH};
+use crate::query_type::QueryType;
+use crate::svcb::SvcParam;
+
+pub const CLASS_IN: u16 = 1;
+pub const CLASS_CH: u16 = 3;
+pub const CLASS_HS: u16 = 4;
+pub const CLASS_NONE: u16 = 254;
+pub const CLASS_ANY: u16 = 255;
+const LOC_DATA_LEN: u16 = 16;
+/// LOC latitude and longitude are offsets from 2^31 in thousandths of a second of arc
+const LOC_EQUATOR_OR_PRIME_MERIDIAN: u32 = 1 << 31;
+const LOC_THOUSANDTHS_OF_ARCSEC_PER_DEGREE: f64 = 3_600_000.0;
+/// LOC altitude is in centimetres above a base 100,000 m below the WGS 84 reference spheroid
+const LOC_ALTITUDE_BASE_CM: i64 = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DnsRecord {
+    Unknown {
+        domain: String,
+        qtype: u16,
+        data_len: u16,
+        data: Vec<u8>,
+        class: u16,
+        ttl: u32,
+    },
+    A {
+        domain: String,
+        addr: Ipv4Addr,
+        class: u16,
+        ttl: u32,
+    },
+    NS {
+        domain: String,
+        host: String,
+        class: u16,
+        ttl: u32,
+    },
+    CNAME {
+        domain: String,
+        host: String,
+        class: u16,
+        ttl: u32,
+    },
+    SOA {
+        domain: String,
+        m_name: String,
+        r_name: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+        class: u16,
+        ttl: u32,
+    },
+    PTR {
+        domain: String,
+        host: String,
+        class: u16,
+        ttl: u32,
+    },
+    MX {
+        domain: String,
+        priority: u16,
+        host: String,
+        class: u16,
+        ttl: u32,
+    },
+    /// Text record, holding one or more character-strings. They're kept as raw bytes, as TXT
+    /// data needn't be text.
+    TXT {
+        domain: String,
+        data: Vec<Vec<u8>>,
+        class: u16,
+        ttl: u32,
+    },
+    AAAA {
+        domain: String,
+        addr: Ipv6Addr,
+        class: u16,
+        ttl: u32,
+    },
+    LOC {
+        domain: String,
+        version: u8,
+        size: u8,
+        horiz_pre: u8,
+        vert_pre: u8,
+        latitude: u32,
+        longitude: u32,
+        altitude: u32,
+        class: u16,
+        ttl: u32,
+    },
+    /// Service location (RFC 2782), pointing at the `target` host and `port` of a service
+    SRV {
+        domain: String,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+        class: u16,
+        ttl: u32,
+    },
+    /// Naming authority pointer (RFC 3403), as used by ENUM and SIP to rewrite a name with
+    /// `regexp` or point at `replacement`
+    NAPTR {
+        domain: String,
+        order: u16,
+        preference: u16,
+        flags: Vec<u8>,
+        services: Vec<u8>,
+        regexp: Vec<u8>,
+        replacement: String,
+        class: u16,
+        ttl: u32,
+    },
+    /// Address prefix list (RFC 3123)
+    APL {
+        domain: String,
+        items: Vec<AplItem>,
+        class: u16,
+        ttl: u32,
+    },
+    /// Service binding record (RFC 9460). A priority of zero is AliasMode, which carries no
+    /// params.
+    SVCB {
+        domain: String,
+        priority: u16,
+        target: String,
+        params: Vec<SvcParam>,
+        class: u16,
+        ttl: u32,
+    },
+    /// SVCB record specialised for HTTP origins, with identical record data
+    HTTPS {
+        domain: String,
+        priority: u16,
+        target: String,
+        params: Vec<SvcParam>,
+        class: u16,
+        ttl: u32,
+    },
+    /// EDNS pseudo-record (RFC 6891), which repurposes the class as the requestor's UDP payload
+    /// size and the TTL as the extended rcode, version and flags
+    OPT {
+        packet_len: u16,
+        flags: u32,
+        options: Vec<EdnsOption>,
+    },
+    /// Transaction signature (RFC 8945), where `domain` is the key name. The class is always ANY
+    /// and the TTL zero, so neither is stored.
+    TSIG {
+        domain: String,
+        algorithm: String,
+        time_signed: u64,
+        fudge: u16,
+        mac: Vec<u8>,
+        original_id: u16,
+        error: u16,
+        other_data: Vec<u8>,
+    },
+}
+
+impl DnsRecord {
+    pub fn from_buffer(buffer: &mut PacketBuffer) -> Result<DnsRecord, String> {
+        let domain = buffer.read_qname()?;
+        let qtype_num = buffer.read_u16()?;
+        let qtype = QueryType::from_num(qtype_num);
+        let class = buffer.read_u16()?;
+        let ttl = buffer.read_u32()?;
+        let data_len = buffer.read_u16()?;
+        let start = buffer.pos();
+        let end = start + data_len as usize;
+
+        let record: Result<DnsRecord, String> = match qtype {
+            QueryType::A => {
+                let addr = Ipv4Addr::from(buffer.read_u32()?);
+                Ok(DnsRecord::A {
+                    domain,
+                    addr,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::NS => {
+                let host = buffer.read_qname()?;
+                Ok(DnsRecord::NS {
+                    domain,
+                    host,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::CNAME => {
+                let host = buffer.read_qname()?;
+                Ok(DnsRecord::CNAME {
+                    domain,
+                    host,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::SOA => Ok(DnsRecord::SOA {
+                domain,
+                m_name: buffer.read_qname()?,
+                r_name: buffer.read_qname()?,
+                serial: buffer.read_u32()?,
+                refresh: buffer.read_u32()?,
+                retry: buffer.read_u32()?,
+                expire: buffer.read_u32()?,
+                minimum: buffer.read_u32()?,
+                class,
+                ttl,
+            }),
+            QueryType::PTR => {
+                let host = buffer.read_qname()?;
+                Ok(DnsRecord::PTR {
+                    domain,
+                    host,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::MX => {
+                let priority = buffer.read_u16()?;
+                let host = buffer.read_qname()?;
+                Ok(DnsRecord::MX {
+                    domain,
+                    priority,
+                    host,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::TXT => {
+                let mut data = Vec::new();
+                while buffer.pos() < end {
+                    let len = buffer.get_range(buffer.pos(), 1)?[0] as usize;
+                    check_item_fits(buffer, 1 + len, end)?;
+                    data.push(read_character_string(buffer)?);
+                }
+                Ok(DnsRecord::TXT {
+                    domain,
+                    data,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::AAAA => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&buffer.read_bytes(16)?);
+                let addr = Ipv6Addr::from(octets);
+                Ok(DnsRecord::AAAA {
+                    domain,
+                    addr,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::LOC => Ok(DnsRecord::LOC {
+                domain,
+                version: buffer.read()?,
+                size: buffer.read()?,
+                horiz_pre: buffer.read()?,
+                vert_pre: buffer.read()?,
+                latitude: buffer.read_u32()?,
+                longitude: buffer.read_u32()?,
+                altitude: buffer.read_u32()?,
+                class,
+                ttl,
+            }),
+            QueryType::SRV => Ok(DnsRecord::SRV {
+                domain,
+                priority: buffer.read_u16()?,
+                weight: buffer.read_u16()?,
+                port: buffer.read_u16()?,
+                target: buffer.read_qname()?,
+                class,
+                ttl,
+            }),
+            QueryType::NAPTR => Ok(DnsRecord::NAPTR {
+                domain,
+                order: buffer.read_u16()?,
+                preference: buffer.read_u16()?,
+                flags: read_character_string(buffer)?,
+                services: read_character_string(buffer)?,
+                regexp: read_character_string(buffer)?,
+                replacement: buffer.read_qname()?,
+                class,
+                ttl,
+            }),
+            QueryType::APL => {
+                let mut items = Vec::new();
+                while buffer.pos() < end {
+                    check_item_fits(buffer, 4, end)?;
+                    let address_len = buffer.get_range(buffer.pos() + 3, 1)?[0] & 0x7F;
+                    check_item_fits(buffer, 4 + address_len as usize, end)?;
+                    items.push(AplItem::from_buffer(buffer)?);
+                }
+                Ok(DnsRecord::APL {
+                    domain,
+                    items,
+                    class,
+                    ttl,
+                })
+            }
+            QueryType::SVCB | QueryType::HTTPS => {
+                let priority = buffer.read_u16()?;
+                let target = buffer.read_qname()?;
+                let mut params = Vec::new();
+                while buffer.pos() < end {
+                    check_key_value_fits(buffer, end)?;
+                    params.push(SvcParam::from_buffer(buffer)?);
+                }
+                if qtype == QueryType::SVCB {
+                    Ok(DnsRecord::SVCB {
+                        domain,
+                        priority,
+                        target,
+                        params,
+                        class,
+                        ttl,
+                    })
+                } else {
+                    Ok(DnsRecord::HTTPS {
+                        domain,
+                        priority,
+                        target,
+                        params,
+                        class,
+                        ttl,
+                    })
+                }
+            }
+            QueryType::OPT => {
+                let mut options = Vec::new();
+                while buffer.pos() < end {
+                    check_key_value_fits(buffer, end)?;
+                    options.push(EdnsOption::from_buffer(buffer)?);
+                }
+                Ok(DnsRecord::OPT {
+                    packet_len: class,
+                    flags: ttl,
+                    options,
+                })
+            }
+            QueryType::TSIG => {
+                let algorithm = buffer.read_qname()?;
+                let time_signed = ((buffer.read_u16()? as u64) << 32) | buffer.read_u32()? as u64;
+                let fudge = buffer.read_u16()?;
+                let mac_len = buffer.read_u16()?;
+                let mac = buffer.read_bytes(mac_len as usize)?;
+                let original_id = buffer.read_u16()?;
+                let error = buffer.read_u16()?;
+                let other_len = buffer.read_u16()?;
+                let other_data = buffer.read_bytes(other_len as usize)?;
+                Ok(DnsRecord::TSIG {
+                    domain,
+                    algorithm,
+                    time_signed,
+                    fudge,
+                    mac,
+                    original_id,
+                    error,
+                    other_data,
+                })
+            }
+            QueryType::Unknown(_)
+            | QueryType::DS
+            | QueryType::RRSIG
+            | QueryType::NSEC
+            | QueryType::DNSKEY
+            | QueryType::NSEC3
+            | QueryType::AXFR
+            | QueryType::ANY => {
+                let data = buffer.read_bytes(data_len as usize)?;
+                Ok(DnsRecord::Unknown {
+                    domain,
+                    qtype: qtype_num,
+                    data_len,
+                    data,
+                    class,
+                    ttl,
+                })
+            }
+        };
+        let record = record?;
+
+        // Record data that doesn't match its declared length would leave the rest of the
+        // packet being read from the wrong position
+        if buffer.pos() != end {
+            let err_str = format!(
+                "Invalid record, record data doesn't match data length: qtype={}, data length={}, read={}",
+                qtype,
+                data_len,
+                buffer.pos() - start
+            );
+            return Err(err_str);
+        }
+        Ok(record)
+    }
+
+    pub fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        match *self {
+            DnsRecord::Unknown {
+                ref domain,
+                qtype,
+                ref data,
+                class,
+                ttl,
+                ..
+            } => {
+                write_preamble(buffer, domain, QueryType::Unknown(qtype), class, ttl)?;
+                buffer.write_u16(data.len() as u16)?;
+                buffer.write_bytes(data)?;
+            }
+            DnsRecord::A {
+                ref domain,
+                ref addr,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::A, class, ttl)?;
+                buffer.write_u16(4)?;
+                buffer.write_u32(u32::from(*addr))?;
+            }
+            DnsRecord::NS {
+                ref domain,
+                ref host,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::NS, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_compressible_qname(host)?;
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::CNAME {
+                ref domain,
+                ref host,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::CNAME, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_compressible_qname(host)?;
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::SOA {
+                ref domain,
+                ref m_name,
+                ref r_name,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::SOA, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_compressible_qname(m_name)?;
+                buffer.write_compressible_qname(r_name)?;
+                buffer.write_u32(serial)?;
+                buffer.write_u32(refresh)?;
+                buffer.write_u32(retry)?;
+                buffer.write_u32(expire)?;
+                buffer.write_u32(minimum)?;
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::PTR {
+                ref domain,
+                ref host,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::PTR, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_compressible_qname(host)?;
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::MX {
+                ref domain,
+                priority,
+                ref host,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::MX, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_u16(priority)?;
+                buffer.write_compressible_qname(host)?;
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::TXT {
+                ref domain,
+                ref data,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::TXT, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                for text in data {
+                    write_character_string(buffer, text)?;
+                }
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::AAAA {
+                ref domain,
+                ref addr,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::AAAA, class, ttl)?;
+                buffer.write_u16(16)?;
+                buffer.write_bytes(&addr.octets())?;
+            }
+            DnsRecord::LOC {
+                ref domain,
+                version,
+                size,
+                horiz_pre,
+                vert_pre,
+                latitude,
+                longitude,
+                altitude,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::LOC, class, ttl)?;
+                buffer.write_u16(LOC_DATA_LEN)?;
+                buffer.write(version)?;
+                buffer.write(size)?;
+                buffer.write(horiz_pre)?;
+                buffer.write(vert_pre)?;
+                buffer.write_u32(latitude)?;
+                buffer.write_u32(longitude)?;
+                buffer.write_u32(altitude)?;
+            }
+            DnsRecord::SRV {
+                ref domain,
+                priority,
+                weight,
+                port,
+                ref target,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::SRV, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_u16(priority)?;
+                buffer.write_u16(weight)?;
+                buffer.write_u16(port)?;
+                // RFC 2782 forbids compressing the target
+                buffer.write_qname(target)?;
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::NAPTR {
+                ref domain,
+                order,
+                preference,
+                ref flags,
+                ref services,
+                ref regexp,
+                ref replacement,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::NAPTR, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_u16(order)?;
+                buffer.write_u16(preference)?;
+                write_character_string(buffer, flags)?;
+                write_character_string(buffer, services)?;
+                write_character_string(buffer, regexp)?;
+                // RFC 3403 forbids compressing the replacement
+                buffer.write_qname(replacement)?;
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::APL {
+                ref domain,
+                ref items,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::APL, class, ttl)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                for item in items {
+                    item.write(buffer)?;
+                }
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::SVCB {
+                ref domain,
+                priority,
+                ref target,
+                ref params,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::SVCB, class, ttl)?;
+                write_svcb_data(buffer, priority, target, params)?;
+            }
+            DnsRecord::HTTPS {
+                ref domain,
+                priority,
+                ref target,
+                ref params,
+                class,
+                ttl,
+            } => {
+                write_preamble(buffer, domain, QueryType::HTTPS, class, ttl)?;
+                write_svcb_data(buffer, priority, target, params)?;
+            }
+            DnsRecord::OPT {
+                packet_len,
+                flags,
+                ref options,
+            } => {
+                buffer.write_qname("")?;
+                buffer.write_u16(QueryType::OPT.to_num())?;
+                buffer.write_u16(packet_len)?;
+                buffer.write_u32(flags)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                for option in options {
+                    option.write(buffer)?;
+                }
+                write_data_len(buffer, len_pos)?;
+            }
+            DnsRecord::TSIG {
+                ref domain,
+                ref algorithm,
+                time_signed,
+                fudge,
+                ref mac,
+                original_id,
+                error,
+                ref other_data,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::TSIG.to_num())?;
+                buffer.write_u16(CLASS_ANY)?;
+                buffer.write_u32(0)?;
+                let len_pos = buffer.pos();
+                buffer.write_u16(0)?;
+                buffer.write_qname(algorithm)?;
+                buffer.write_u16((time_signed >> 32) as u16)?;
+                buffer.write_u32(time_signed as u32)?;
+                buffer.write_u16(fudge)?;
+                buffer.write_u16(mac.len() as u16)?;
+                buffer.write_bytes(mac)?;
+                buffer.write_u16(original_id)?;
+                buffer.write_u16(error)?;
+                buffer.write_u16(other_data.len() as u16)?;
+                buffer.write_bytes(other_data)?;
+                write_data_len(buffer, len_pos)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        match *self {
+            DnsRecord::Unknown { ref domain, .. }
+            | DnsRecord::A { ref domain, .. }
+            | DnsRecord::NS { ref domain, .. }
+            | DnsRecord::CNAME { ref domain, .. }
+            | DnsRecord::SOA { ref domain, .. }
+            | DnsRecord::PTR { ref domain, .. }
+            | DnsRecord::MX { ref domain, .. }
+            | DnsRecord::TXT { ref domain, .. }
+            | DnsRecord::AAAA { ref domain, .. }
+            | DnsRecord::LOC { ref domain, .. }
+            | DnsRecord::SRV { ref domain, .. }
+            | DnsRecord::NAPTR { ref domain, .. }
+            | DnsRecord::APL { ref domain, .. }
+            | DnsRecord::SVCB { ref domain, .. }
+            | DnsRecord::HTTPS { ref domain, .. }
+            | DnsRecord::TSIG { ref domain, .. } => domain,
+            DnsRecord::OPT { .. } => "",
+        }
+    }
+
+    pub fn qtype(&self) -> QueryType {
+        match *self {
+            DnsRecord::Unknown { qtype, .. } => QueryType::from_num(qtype),
+            DnsRecord::A { .. } => QueryType::A,
+            DnsRecord::NS { .. } => QueryType::NS,
+            DnsRecord::CNAME { .. } => QueryType::CNAME,
+            DnsRecord::SOA { .. } => QueryType::SOA,
+            DnsRecord::PTR { .. } => QueryType::PTR,
+            DnsRecord::MX { .. } => QueryType::MX,
+            DnsRecord::TXT { .. } => QueryType::TXT,
+            DnsRecord::AAAA { .. } => QueryType::AAAA,
+            DnsRecord::LOC { .. } => QueryType::LOC,
+            DnsRecord::SRV { .. } => QueryType::SRV,
+            DnsRecord::NAPTR { .. } => QueryType::NAPTR,
+            DnsRecord::APL { .. } => QueryType::APL,
+            DnsRecord::SVCB { .. } => QueryType::SVCB,
+            DnsRecord::HTTPS { .. } => QueryType::HTTPS,
+            DnsRecord::OPT { .. } => QueryType::OPT,
+            DnsRecord::TSIG { .. } => QueryType::TSIG,
+        }
+    }
+
+    /// Names in the record that may be written compressed: the owner name, and the names in
+    /// the record data of types defined in RFC 1035
+    pub(crate) fn compressible_names(&self) -> Vec<&str> {
+        match *self {
+            DnsRecord::NS { ref host, .. }
+            | DnsRecord::CNAME { ref host, .. }
+            | DnsRecord::PTR { ref host, .. }
+            | DnsRecord::MX { ref host, .. } => vec![self.name(), host],
+            DnsRecord::SOA {
+                ref m_name,
+                ref r_name,
+                ..
+            } => vec![self.name(), m_name, r_name],
+            DnsRecord::OPT { .. } | DnsRecord::TSIG { .. } => Vec::new(),
+            _ => vec![self.name()],
+        }
+    }
+
+    /// TTL of the record, which is zero for OPT records as their TTL field holds flags instead,
+    /// and for TSIG records which are never cached
+    pub fn ttl(&self) -> u32 {
+        match *self {
+            DnsRecord::Unknown { ttl, .. }
+            | DnsRecord::A { ttl, .. }
+            | DnsRecord::NS { ttl, .. }
+            | DnsRecord::CNAME { ttl, .. }
+            | DnsRecord::SOA { ttl, .. }
+            | DnsRecord::PTR { ttl, .. }
+            | DnsRecord::MX { ttl, .. }
+            | DnsRecord::TXT { ttl, .. }
+            | DnsRecord::AAAA { ttl, .. }
+            | DnsRecord::LOC { ttl, .. }
+            | DnsRecord::SRV { ttl, .. }
+            | DnsRecord::NAPTR { ttl, .. }
+            | DnsRecord::APL { ttl, .. }
+            | DnsRecord::SVCB { ttl, .. }
+            | DnsRecord::HTTPS { ttl, .. } => ttl,
+            DnsRecord::OPT { .. } | DnsRecord::TSIG { .. } => 0,
+        }
+    }
+
+    /// Class of the record, which is IN for OPT records as their class field holds the sender's
+    /// UDP payload size instead, and ANY for TSIG records
+    pub fn class(&self) -> u16 {
+        match *self {
+            DnsRecord::Unknown { class, .. }
+            | DnsRecord::A { class, .. }
+            | DnsRecord::NS { class, .. }
+            | DnsRecord::CNAME { class, .. }
+            | DnsRecord::SOA { class, .. }
+            | DnsRecord::PTR { class, .. }
+            | DnsRecord::MX { class, .. }
+            | DnsRecord::TXT { class, .. }
+            | DnsRecord::AAAA { class, .. }
+            | DnsRecord::LOC { class, .. }
+            | DnsRecord::SRV { class, .. }
+            | DnsRecord::NAPTR { class, .. }
+            | DnsRecord::APL { class, .. }
+            | DnsRecord::SVCB { class, .. }
+            | DnsRecord::HTTPS { class, .. } => class,
+            DnsRecord::OPT { .. } => CLASS_IN,
+            DnsRecord::TSIG { .. } => CLASS_ANY,
+        }
+    }
+
+    /// Whether two records have the same name, type and data, regardless of their TTLs
+    pub fn is_duplicate_of(&self, other: &DnsRecord) -> bool {
+        let mut record = self.clone();
+        let mut other = other.clone();
+        if let (Some(ttl), Some(other_ttl)) = (record.ttl_mut(), other.ttl_mut()) {
+            *ttl = 0;
+            *other_ttl = 0;
+        }
+        record == other
+    }
+
+    fn ttl_mut(&mut self) -> Option<&mut u32> {
+        match *self {
+            DnsRecord::Unknown { ref mut ttl, .. }
+            | DnsRecord::A { ref mut ttl, .. }
+            | DnsRecord::NS { ref mut ttl, .. }
+            | DnsRecord::CNAME { ref mut ttl, .. }
+            | DnsRecord::SOA { ref mut ttl, .. }
+            | DnsRecord::PTR { ref mut ttl, .. }
+            | DnsRecord::MX { ref mut ttl, .. }
+            | DnsRecord::TXT { ref mut ttl, .. }
+            | DnsRecord::AAAA { ref mut ttl, .. }
+            | DnsRecord::LOC { ref mut ttl, .. }
+            | DnsRecord::SRV { ref mut ttl, .. }
+            | DnsRecord::NAPTR { ref mut ttl, .. }
+            | DnsRecord::APL { ref mut ttl, .. }
+            | DnsRecord::SVCB { ref mut ttl, .. }
+            | DnsRecord::HTTPS { ref mut ttl, .. } => Some(ttl),
+            DnsRecord::OPT { .. } | DnsRecord::TSIG { .. } => None,
+        }
+    }
+
+    /// Host that an MX, NS or SRV record points clients at, whose addresses are worth adding to
+    /// the additional section of a response carrying it
+    pub fn target_host(&self) -> Option<&str> {
+        match *self {
+            DnsRecord::NS { ref host, .. } | DnsRecord::MX { ref host, .. } => Some(host),
+            DnsRecord::SRV { ref target, .. } => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Latitude and longitude of a LOC record in degrees, with north and east being positive
+    pub fn loc_degrees(&self) -> Option<(f64, f64)> {
+        match *self {
+            DnsRecord::LOC {
+                latitude,
+                longitude,
+                ..
+            } => Some((loc_raw_to_degrees(latitude), loc_raw_to_degrees(longitude))),
+            _ => None,
+        }
+    }
+
+    /// Record in the RFC 1035 master file presentation format, with names written as absolute
+    /// names. Record data this crate keeps as raw bytes, and the OPT and TSIG meta-records, are
+    /// written in the generic `\# length hex` format of RFC 3597.
+    pub fn to_presentation(&self) -> String {
+        let (class, ttl) = match *self {
+            DnsRecord::OPT {
+                packet_len, flags, ..
+            } => (format!("CLASS{}", packet_len), flags),
+            DnsRecord::TSIG { .. } => ("ANY".to_string(), 0),
+            _ => (class_mnemonic(self.class()), self.ttl()),
+        };
+        format!(
+            "{} {} {} {} {}",
+            absolute_name(self.name()),
+            ttl,
+            class,
+            self.qtype(),
+            self.presentation_data()
+        )
+    }
+
+    fn presentation_data(&self) -> String {
+        match *self {
+            DnsRecord::Unknown { ref data, .. } => generic_data(data),
+            DnsRecord::A { addr, .. } => addr.to_string(),
+            DnsRecord::NS { ref host, .. }
+            | DnsRecord::CNAME { ref host, .. }
+            | DnsRecord::PTR { ref host, .. } => absolute_name(host),
+            DnsRecord::SOA {
+                ref m_name,
+                ref r_name,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ..
+            } => format!(
+                "{} {} {} {} {} {} {}",
+                absolute_name(m_name),
+                absolute_name(r_name),
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum
+            ),
+            DnsRecord::MX {
+                priority, ref host, ..
+            } => format!("{} {}", priority, absolute_name(host)),
+            DnsRecord::TXT { ref data, .. } => {
+                let strings: Vec<String> = data.iter().map(|text| character_string(text)).collect();
+                strings.join(" ")
+            }
+            DnsRecord::AAAA { addr, .. } => addr.to_string(),
+            DnsRecord::LOC {
+                size,
+                horiz_pre,
+                vert_pre,
+                latitude,
+                longitude,
+                altitude,
+                ..
+            } => format!(
+                "{} {} {} {} {} {}",
+                loc_angle(latitude, 'N', 'S'),
+                loc_angle(longitude, 'E', 'W'),
+                loc_altitude(altitude),
+                loc_size(size),
+                loc_size(horiz_pre),
+                loc_size(vert_pre)
+            ),
+            DnsRecord::SRV {
+                priority,
+                weight,
+                port,
+                ref target,
+                ..
+            } => format!("{} {} {} {}", priority, weight, port, absolute_name(target)),
+            DnsRecord::NAPTR {
+                order,
+                preference,
+                ref flags,
+                ref services,
+                ref regexp,
+                ref replacement,
+                ..
+            } => format!(
+                "{} {} {} {} {} {}",
+                order,
+                preference,
+                character_string(flags),
+                character_string(services),
+                character_string(regexp),
+                absolute_name(replacement)
+            ),
+            DnsRecord::APL { ref items, .. } => {
+                let items: Vec<String> = items.iter().map(AplItem::to_presentation).collect();
+                items.join(" ")
+            }
+            DnsRecord::SVCB {
+                priority,
+                ref target,
+                ref params,
+                ..
+            }
+            | DnsRecord::HTTPS {
+                priority,
+                ref target,
+                ref params,
+                ..
+            } => {
+                let mut data = format!("{} {}", priority, absolute_name(target));
+                for param in params {
+                    data.push(' ');
+                    data.push_str(&param.to_presentation());
+                }
+                data
+            }
+            DnsRecord::OPT { .. } | DnsRecord::TSIG { .. } => generic_data(&self.written_data()),
+        }
+    }
+
+    /// Record data as written to the wire. Records too large to write have no data to show.
+    fn written_data(&self) -> Vec<u8> {
+        let mut buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        let data = self.write(&mut buffer).and_then(|_| {
+            buffer.seek(0)?;
+            buffer.read_qname()?;
+            // Skip the type, class and TTL
+            buffer.step(8)?;
+            let data_len = buffer.read_u16()?;
+            buffer.read_bytes(data_len as usize)
+        });
+        data.unwrap_or_default()
+    }
+}
+
+/// Address prefix in an APL record, where `address` holds only the significant bytes of the
+/// address with trailing zero bytes dropped
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AplItem {
+    /// Address family number from IANA, 1 for IPv4 and 2 for IPv6
+    pub family: u16,
+    pub prefix: u8,
+    pub negation: bool,
+    pub address: Vec<u8>,
+}
+
+impl AplItem {
+    pub fn from_buffer(buffer: &mut PacketBuffer) -> Result<AplItem, String> {
+        let family = buffer.read_u16()?;
+        let prefix = buffer.read()?;
+        let negation_and_len = buffer.read()?;
+        let address = buffer.read_bytes((negation_and_len & 0x7F) as usize)?;
+        Ok(AplItem {
+            family,
+            prefix,
+            negation: negation_and_len & 0x80 != 0,
+            address,
+        })
+    }
+
+    pub fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        if self.address.len() > 0x7F {
+            let err_str = format!(
+                "Invalid APL item, address exceeds 127 bytes: len={}",
+                self.address.len()
+            );
+            return Err(err_str);
+        }
+        buffer.write_u16(self.family)?;
+        buffer.write(self.prefix)?;
+        buffer.write((self.negation as u8) << 7 | self.address.len() as u8)?;
+        buffer.write_bytes(&self.address)
+    }
+
+    /// Item in the `[!]family:address/prefix` form of RFC 3123, with the address bytes that
+    /// were dropped on the wire restored as zeros
+    fn to_presentation(&self) -> String {
+        let negation = if self.negation { "!" } else { "" };
+        let len = self.address.len();
+        let address = match (self.family, len) {
+            (1, 0..=4) => {
+                let mut octets = [0; 4];
+                octets[..len].copy_from_slice(&self.address);
+                Ipv4Addr::from(octets).to_string()
+            }
+            (2, 0..=16) => {
+                let mut octets = [0; 16];
+                octets[..len].copy_from_slice(&self.address);
+                Ipv6Addr::from(octets).to_string()
+            }
+            _ => hex(&self.address),
+        };
+        format!("{}{}:{}/{}", negation, self.family, address, self.prefix)
+    }
+}
+
+fn loc_raw_to_degrees(raw: u32) -> f64 {
+    (raw as i64 - LOC_EQUATOR_OR_PRIME_MERIDIAN as i64) as f64
+        / LOC_THOUSANDTHS_OF_ARCSEC_PER_DEGREE
+}
+
+/// LOC latitude or longitude as degrees, minutes and seconds followed by the hemisphere
+fn loc_angle(raw: u32, positive: char, negative: char) -> String {
+    let offset = raw as i64 - LOC_EQUATOR_OR_PRIME_MERIDIAN as i64;
+    let hemisphere = if offset < 0 { negative } else { positive };
+    let thousandths = offset.unsigned_abs();
+    format!(
+        "{} {} {}.{:03} {}",
+        thousandths / LOC_THOUSANDTHS_OF_ARCSEC_PER_DEGREE as u64,
+        thousandths / 60_000 % 60,
+        thousandths / 1000 % 60,
+        thousandths % 1000,
+        hemisphere
+    )
+}
+
+fn loc_altitude(raw: u32) -> String {
+    let cm = raw as i64 - LOC_ALTITUDE_BASE_CM;
+    let sign = if cm < 0 { "-" } else { "" };
+    let cm = cm.unsigned_abs();
+    format!("{}{}.{:02}m", sign, cm / 100, cm % 100)
+}
+
+/// LOC size or precision, which is encoded as a mantissa and power of ten in centimetres
+fn loc_size(raw: u8) -> String {
+    let cm = (raw >> 4) as u64 * 10u64.pow((raw & 0x0F) as u32);
+    if cm.is_multiple_of(100) {
+        format!("{}m", cm / 100)
+    } else {
+        format!("{}.{:02}m", cm / 100, cm % 100)
+    }
+}
+
+/// Name with the trailing dot that marks it as absolute in presentation format
+/// Presentation form of a class (RFC 1035 section 5.1, RFC 3597 section 5)
+pub(crate) fn class_mnemonic(class: u16) -> String {
+    match class {
+        CLASS_IN => "IN".to_string(),
+        CLASS_CH => "CH".to_string(),
+        CLASS_HS => "HS".to_string(),
+        CLASS_NONE => "NONE".to_string(),
+        CLASS_ANY => "ANY".to_string(),
+        _ => format!("CLASS{}", class),
+    }
+}
+
+pub(crate) fn absolute_name(name: &str) -> String {
+    format!("{}.", name)
+}
+
+/// Whether `name` is `zone` or a name below it, comparing labels without regard to ASCII case.
+/// An empty zone is the root, which every name is in.
+pub(crate) fn in_zone(name: &str, zone: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+    zone.is_empty() || name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+/// Quoted character-string with quotes and backslashes escaped, and bytes outside printable
+/// ASCII written in `\DDD` form
+pub(crate) fn character_string(text: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in text {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(byte as char);
+            }
+            0x20..=0x7E => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\{:03}", byte)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn generic_data(data: &[u8]) -> String {
+    if data.is_empty() {
+        return "\\# 0".to_string();
+    }
+    format!("\\# {} {}", data.len(), hex(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// Write the fields common to all records that precede the record data length
+fn write_preamble(
+    buffer: &mut PacketBuffer,
+    domain: &str,
+    qtype: QueryType,
+    class: u16,
+    ttl: u32,
+) -> Result<(), String> {
+    buffer.write_compressible_qname(domain)?;
+    buffer.write_u16(qtype.to_num())?;
+    buffer.write_u16(class)?;
+    buffer.write_u32(ttl)?;
+    Ok(())
+}
+
+/// Check that an item of `item_len` bytes at the buffer's position ends within record data
+/// that ends at `end`, so that a malformed item can't run on into the next record
+pub(crate) fn check_item_fits(
+    buffer: &PacketBuffer,
+    item_len: usize,
+    end: usize,
+) -> Result<(), String> {
+    if buffer.pos() + item_len > end {
+        let err_str = format!(
+            "Invalid record data, item runs past end of record data: pos={}, item length={}, end={}",
+            buffer.pos(),
+            item_len,
+            end
+        );
+        return Err(err_str);
+    }
+    Ok(())
+}
+
+/// Check that a key-value item, such as an SvcParam or EDNS option, fits within record data
+/// ending at `end`. Both are a 2 byte key and 2 byte value length followed by the value.
+pub(crate) fn check_key_value_fits(buffer: &PacketBuffer, end: usize) -> Result<(), String> {
+    check_item_fits(buffer, 4, end)?;
+    let len = buffer.get_range(buffer.pos() + 2, 2)?;
+    let value_len = ((len[0] as usize) << 8) | len[1] as usize;
+    check_item_fits(buffer, 4 + value_len, end)
+}
+
+/// Read a length-prefixed character-string from record data
+fn read_character_string(buffer: &mut PacketBuffer) -> Result<Vec<u8>, String> {
+    let len = buffer.read()? as usize;
+    buffer.read_bytes(len)
+}
+
+fn write_character_string(buffer: &mut PacketBuffer, text: &[u8]) -> Result<(), String> {
+    if text.len() > 0xFF {
+        let err_str = format!(
+            "Invalid character-string, exceeds 255 bytes: len={}",
+            text.len()
+        );
+        return Err(err_str);
+    }
+    buffer.write(text.len() as u8)?;
+    buffer.write_bytes(text)
+}
+
+/// Write the record data shared by SVCB and HTTPS records
+fn write_svcb_data(
+    buffer: &mut PacketBuffer,
+    priority: u16,
+    target: &str,
+    params: &[SvcParam],
+) -> Result<(), String> {
+    let len_pos = buffer.pos();
+    buffer.write_u16(0)?;
+    buffer.write_u16(priority)?;
+    buffer.write_qname(target)?;
+    for param in params {
+        param.write(buffer)?;
+    }
+    write_data_len(buffer, len_pos)
+}
+
+/// Fill in the record data length at `len_pos` once the variable-length data following it has
+/// been written
+fn write_data_len(buffer: &mut PacketBuffer, len_pos: usize) -> Result<(), String> {
+    let data_len = buffer.pos() - (len_pos + 2);
+    buffer.set_u16(len_pos, data_len as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edns::ClientSubnet;
+    use crate::packet::{DnsPacket, PACKET_BYTES_LENGTH};
+    use crate::question::DnsQuestion;
+
+    fn round_trip(record: &DnsRecord) -> DnsRecord {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        record.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        DnsRecord::from_buffer(&mut packet_buffer).unwrap()
+    }
+
+    #[test]
+    fn parse_a_record_from_buffer() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x06, b'g', b'o', b'o', b'g', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, // domain
+            0x00, 0x01, // qtype
+            0x00, 0x01, // class
+            0x00, 0x00, 0x01, 0x25, // ttl
+            0x00, 0x04, // data length
+            0xD8, 0x3A, 0xD3, 0x8E, // address
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_record = DnsRecord::A {
+            domain: "google.com".to_string(),
+            addr: Ipv4Addr::new(216, 58, 211, 142),
+            class: CLASS_IN,
+            ttl: 293,
+        };
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_record)
+        );
+        assert_eq!(record_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn known_records_survive_round_trip() {
+        let records = [
+            DnsRecord::A {
+                domain: "google.com".to_string(),
+                addr: Ipv4Addr::new(216, 58, 211, 142),
+                class: CLASS_IN,
+                ttl: 293,
+            },
+            DnsRecord::NS {
+                domain: "google.com".to_string(),
+                host: "ns1.google.com".to_string(),
+                class: CLASS_IN,
+                ttl: 3600,
+            },
+            DnsRecord::CNAME {
+                domain: "www.example.com".to_string(),
+                host: "example.com".to_string(),
+                class: CLASS_IN,
+                ttl: 60,
+            },
+            DnsRecord::SOA {
+                domain: "example.com".to_string(),
+                m_name: "ns.icann.org".to_string(),
+                r_name: "noc.dns.icann.org".to_string(),
+                serial: 2024081465,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 3600,
+                class: CLASS_IN,
+                ttl: 3600,
+            },
+            DnsRecord::PTR {
+                domain: "1.2.0.192.in-addr.arpa".to_string(),
+                host: "example.com".to_string(),
+                class: CLASS_IN,
+                ttl: 60,
+            },
+            DnsRecord::MX {
+                domain: "google.com".to_string(),
+                priority: 10,
+                host: "smtp.google.com".to_string(),
+                class: CLASS_IN,
+                ttl: 300,
+            },
+            DnsRecord::AAAA {
+                domain: "google.com".to_string(),
+                addr: "2a00:1450:4009:81f::200e".parse().unwrap(),
+                class: CLASS_IN,
+                ttl: 300,
+            },
+        ];
+        for record in records {
+            assert_eq!(record, round_trip(&record));
+        }
+    }
+
+    #[test]
+    fn unknown_record_data_preserved_byte_for_byte() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x03, b'f', b'o', b'o', 0x00, // domain
+            0x00, 0x63, // qtype 99
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x05, // data length
+            0xDE, 0xAD, 0x00, 0xBE, 0xEF, // data
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let record = DnsRecord::from_buffer(&mut packet_buffer).unwrap();
+        let expected_record = DnsRecord::Unknown {
+            domain: "foo".to_string(),
+            qtype: 99,
+            data_len: 5,
+            data: vec![0xDE, 0xAD, 0x00, 0xBE, 0xEF],
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(expected_record, record);
+
+        let mut out_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        record.write(&mut out_buffer).unwrap();
+        assert_eq!(record_bytes.len(), out_buffer.pos());
+        assert_eq!(
+            true,
+            out_buffer
+                .get_range(0, record_bytes.len())
+                .is_ok_and(|val| val == record_bytes)
+        );
+    }
+
+    fn cambridge_loc_record() -> DnsRecord {
+        // cambridge-net.kei.com. LOC 42 21 54 N 71 06 18 W -24m 30m (example from RFC 1876)
+        DnsRecord::LOC {
+            domain: "cambridge-net.kei.com".to_string(),
+            version: 0,
+            size: 0x33,
+            horiz_pre: 0x16,
+            vert_pre: 0x13,
+            latitude: 2_299_997_648,
+            longitude: 1_891_505_648,
+            altitude: 9_997_600,
+            class: CLASS_IN,
+            ttl: 3600,
+        }
+    }
+
+    #[test]
+    fn loc_record_survives_round_trip() {
+        let record = cambridge_loc_record();
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn loc_record_data_is_sixteen_bytes() {
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        cambridge_loc_record().write(&mut packet_buffer).unwrap();
+        // 23 bytes of domain, 10 bytes of preamble and length, 16 bytes of data
+        assert_eq!(23 + 10 + 16, packet_buffer.pos());
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(31, 2)
+                .is_ok_and(|val| val == [0x00, 0x10])
+        );
+    }
+
+    #[test]
+    fn loc_record_decoded_to_degrees() {
+        let (latitude, longitude) = cambridge_loc_record().loc_degrees().unwrap();
+        assert_eq!(true, (latitude - 42.365).abs() < 1e-9);
+        assert_eq!(true, (longitude - -71.105).abs() < 1e-9);
+    }
+
+    #[test]
+    fn non_loc_record_has_no_degrees() {
+        let record = DnsRecord::A {
+            domain: "google.com".to_string(),
+            addr: Ipv4Addr::new(216, 58, 211, 142),
+            class: CLASS_IN,
+            ttl: 293,
+        };
+        assert_eq!(None, record.loc_degrees());
+    }
+
+    #[test]
+    fn opt_record_with_client_subnet_survives_round_trip() {
+        let subnet = ClientSubnet::from_addr("198.51.100.77".parse().unwrap(), 24).unwrap();
+        let record = DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: vec![EdnsOption::ClientSubnet(subnet)],
+        };
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn parse_opt_record_with_client_subnet() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x00, // root domain
+            0x00, 0x29, // qtype
+            0x04, 0xD0, // UDP payload size
+            0x00, 0x00, 0x00, 0x00, // extended rcode, version, flags
+            0x00, 0x0B, // data length
+            0x00, 0x08, 0x00, 0x07, 0x00, 0x01, 0x18, 0x00, 0xC6, 0x33, 0x64, // client subnet
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_record = DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0,
+            options: vec![EdnsOption::ClientSubnet(ClientSubnet {
+                family: 1,
+                source_prefix_len: 24,
+                scope_prefix_len: 0,
+                address: vec![198, 51, 100],
+            })],
+        };
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_record)
+        );
+        assert_eq!(record_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn records_differing_only_by_ttl_are_duplicates() {
+        let record = DnsRecord::MX {
+            domain: "google.com".to_string(),
+            priority: 10,
+            host: "smtp.google.com".to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        let other = DnsRecord::MX {
+            domain: "google.com".to_string(),
+            priority: 10,
+            host: "smtp.google.com".to_string(),
+            class: CLASS_IN,
+            ttl: 60,
+        };
+        let different_data = DnsRecord::MX {
+            domain: "google.com".to_string(),
+            priority: 20,
+            host: "smtp.google.com".to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        assert_eq!(true, record.is_duplicate_of(&other));
+        assert_eq!(false, record.is_duplicate_of(&different_data));
+    }
+
+    #[test]
+    fn common_fields_accessible_across_variants() {
+        let records = [
+            (
+                DnsRecord::A {
+                    domain: "google.com".to_string(),
+                    addr: Ipv4Addr::new(216, 58, 211, 142),
+                    class: CLASS_IN,
+                    ttl: 293,
+                },
+                "google.com",
+                QueryType::A,
+                293,
+            ),
+            (
+                DnsRecord::MX {
+                    domain: "gmail.com".to_string(),
+                    priority: 10,
+                    host: "smtp.google.com".to_string(),
+                    class: CLASS_IN,
+                    ttl: 300,
+                },
+                "gmail.com",
+                QueryType::MX,
+                300,
+            ),
+            (
+                cambridge_loc_record(),
+                "cambridge-net.kei.com",
+                QueryType::LOC,
+                3600,
+            ),
+            (
+                DnsRecord::Unknown {
+                    domain: "foo".to_string(),
+                    qtype: 99,
+                    data_len: 0,
+                    data: Vec::new(),
+                    class: CLASS_IN,
+                    ttl: 60,
+                },
+                "foo",
+                QueryType::Unknown(99),
+                60,
+            ),
+            (
+                DnsRecord::OPT {
+                    packet_len: 1232,
+                    flags: 0x8000,
+                    options: Vec::new(),
+                },
+                "",
+                QueryType::OPT,
+                0,
+            ),
+        ];
+        for (record, name, qtype, ttl) in records {
+            assert_eq!(name, record.name());
+            assert_eq!(qtype, record.qtype());
+            assert_eq!(ttl, record.ttl());
+        }
+    }
+
+    #[test]
+    fn https_record_with_alpn_survives_round_trip() {
+        let record = DnsRecord::HTTPS {
+            domain: "cloudflare.com".to_string(),
+            priority: 1,
+            target: "".to_string(),
+            params: vec![
+                SvcParam::Alpn(vec!["h2".to_string(), "h3".to_string()]),
+                SvcParam::Ipv4Hint(vec![Ipv4Addr::new(104, 16, 132, 229)]),
+            ],
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn alias_mode_svcb_record_survives_round_trip() {
+        let record = DnsRecord::SVCB {
+            domain: "_8443._foo.api.example.com".to_string(),
+            priority: 0,
+            target: "svc4.example.net".to_string(),
+            params: vec![],
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn parse_https_record_with_unknown_param() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // domain
+            0x00, 0x41, // qtype
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x0D, // data length
+            0x00, 0x01, // priority
+            0x00, // target
+            0x00, 0x03, 0x00, 0x02, 0x01, 0xBB, // port=443
+            0xFF, 0x00, 0x00, 0x00, // unknown key with empty value
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_record = DnsRecord::HTTPS {
+            domain: "example.com".to_string(),
+            priority: 1,
+            target: "".to_string(),
+            params: vec![
+                SvcParam::Port(443),
+                SvcParam::Unknown {
+                    key: 0xFF00,
+                    value: vec![],
+                },
+            ],
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_record)
+        );
+        assert_eq!(record_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn tsig_record_survives_round_trip() {
+        let record = DnsRecord::TSIG {
+            domain: "transfer-key".to_string(),
+            algorithm: "hmac-sha256".to_string(),
+            time_signed: 0x0001_6553_F100,
+            fudge: 300,
+            mac: vec![0xAB; 32],
+            original_id: 0x1234,
+            error: 0,
+            other_data: vec![],
+        };
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn apl_record_with_ipv4_and_ipv6_items_survives_round_trip() {
+        let record = DnsRecord::APL {
+            domain: "policy.example.com".to_string(),
+            items: vec![
+                AplItem {
+                    family: 1,
+                    prefix: 21,
+                    negation: false,
+                    address: vec![192, 168, 32],
+                },
+                AplItem {
+                    family: 1,
+                    prefix: 28,
+                    negation: true,
+                    address: vec![192, 168, 38],
+                },
+                AplItem {
+                    family: 2,
+                    prefix: 32,
+                    negation: false,
+                    address: vec![0x20, 0x01, 0x0D, 0xB8],
+                },
+            ],
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn parse_apl_record_with_negated_item() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // domain
+            0x00, 0x2A, // qtype
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x0E, // data length
+            0x00, 0x01, 0x15, 0x03, 0xC0, 0xA8, 0x20, // 1:192.168.32.0/21
+            0x00, 0x01, 0x1C, 0x83, 0xC0, 0xA8, 0x26, // !1:192.168.38.0/28
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_record = DnsRecord::APL {
+            domain: "example.com".to_string(),
+            items: vec![
+                AplItem {
+                    family: 1,
+                    prefix: 21,
+                    negation: false,
+                    address: vec![192, 168, 32],
+                },
+                AplItem {
+                    family: 1,
+                    prefix: 28,
+                    negation: true,
+                    address: vec![192, 168, 38],
+                },
+            ],
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_record)
+        );
+        assert_eq!(record_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn sip_naptr_record_survives_round_trip() {
+        let record = DnsRecord::NAPTR {
+            domain: "example.com".to_string(),
+            order: 100,
+            preference: 10,
+            flags: b"S".to_vec(),
+            services: b"SIP+D2U".to_vec(),
+            regexp: b"".to_vec(),
+            replacement: "_sip._udp.example.com".to_string(),
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn enum_naptr_record_regexp_survives_round_trip() {
+        let record = DnsRecord::NAPTR {
+            domain: "4.3.2.1.5.5.5.0.0.8.1.e164.arpa".to_string(),
+            order: 100,
+            preference: 10,
+            flags: b"u".to_vec(),
+            services: b"E2U+sip".to_vec(),
+            regexp: b"!^.*$!sip:info@example.com!".to_vec(),
+            replacement: "".to_string(),
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(record, round_trip(&record));
+    }
+
+    #[test]
+    fn naptr_record_with_non_ascii_regexp_survives_round_trip() {
+        let record = DnsRecord::NAPTR {
+            domain: "example.com".to_string(),
+            order: 100,
+            preference: 10,
+            flags: b"u".to_vec(),
+            services: b"E2U+sip".to_vec(),
+            regexp: b"!^.*$!sip:\xFF@example.com!".to_vec(),
+            replacement: "".to_string(),
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(record, round_trip(&record));
+        assert_eq!(
+            "example.com. 3600 IN NAPTR 100 10 \"u\" \"E2U+sip\" \"!^.*$!sip:\\255@example.com!\" .",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn return_error_if_naptr_string_exceeds_255_bytes() {
+        let record = DnsRecord::NAPTR {
+            domain: "example.com".to_string(),
+            order: 100,
+            preference: 10,
+            flags: b"u".to_vec(),
+            services: b"E2U+sip".to_vec(),
+            regexp: vec![b'a'; 256],
+            replacement: "".to_string(),
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        let expected_str = "Invalid character-string, exceeds 255 bytes: len=256";
+        assert_eq!(
+            true,
+            record
+                .write(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn a_record_presentation() {
+        let record = DnsRecord::A {
+            domain: "google.com".to_string(),
+            addr: Ipv4Addr::new(216, 58, 211, 142),
+            class: CLASS_IN,
+            ttl: 293,
+        };
+        assert_eq!(
+            "google.com. 293 IN A 216.58.211.142",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn mx_record_presentation() {
+        let record = DnsRecord::MX {
+            domain: "example.com".to_string(),
+            priority: 10,
+            host: "mail.example.com".to_string(),
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(
+            "example.com. 3600 IN MX 10 mail.example.com.",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn soa_record_presentation() {
+        let record = DnsRecord::SOA {
+            domain: "example.com".to_string(),
+            m_name: "ns1.example.com".to_string(),
+            r_name: "hostmaster.example.com".to_string(),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+            class: CLASS_IN,
+            ttl: 86400,
+        };
+        assert_eq!(
+            "example.com. 86400 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn parse_txt_record_with_two_strings() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // domain
+            0x00, 0x10, // qtype
+            0x00, 0x01, // class
+            0x00, 0x00, 0x01, 0x2C, // ttl
+            0x00, 0x0A, // data length
+            0x05, b'h', b'e', b'l', b'l', b'o', // first string
+            0x03, b'a', b'=', b'1', // second string
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_record = DnsRecord::TXT {
+            domain: "example.com".to_string(),
+            data: vec![b"hello".to_vec(), b"a=1".to_vec()],
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_record)
+        );
+        assert_eq!(record_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn return_error_if_txt_string_runs_past_data_length() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // domain
+            0x00, 0x10, // qtype
+            0x00, 0x01, // class
+            0x00, 0x00, 0x01, 0x2C, // ttl
+            0x00, 0x04, // data length
+            0x05, b'h', b'e', b'l', b'l', b'o', // string overruns data length
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str =
+            "Invalid record data, item runs past end of record data: pos=23, item length=6, end=27";
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn txt_record_survives_round_trip() {
+        let record = DnsRecord::TXT {
+            domain: "example.com".to_string(),
+            data: vec![b"v=spf1 -all".to_vec(), Vec::new(), vec![0xFF, 0xFE]],
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        record.write(&mut packet_buffer).unwrap();
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer).is_ok_and(|val| val == record)
+        );
+    }
+
+    #[test]
+    fn txt_record_presentation_quotes_and_escapes_strings() {
+        let record = DnsRecord::TXT {
+            domain: "example.com".to_string(),
+            data: vec![
+                b"say \"hi\"".to_vec(),
+                b"back\\slash\ttab".to_vec(),
+                vec![0xFF, 0xFE],
+            ],
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        assert_eq!(
+            "example.com. 300 IN TXT \"say \\\"hi\\\"\" \"back\\\\slash\\009tab\" \"\\255\\254\"",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn loc_record_presentation() {
+        assert_eq!(
+            "cambridge-net.kei.com. 3600 IN LOC 42 21 54.000 N 71 6 18.000 W -24.00m 30m 10000m 10m",
+            cambridge_loc_record().to_presentation()
+        );
+    }
+
+    #[test]
+    fn naptr_record_presentation_quotes_strings() {
+        let record = DnsRecord::NAPTR {
+            domain: "4.3.2.1.5.5.5.0.0.8.1.e164.arpa".to_string(),
+            order: 100,
+            preference: 10,
+            flags: b"u".to_vec(),
+            services: b"E2U+sip".to_vec(),
+            regexp: b"!^.*$!sip:\"info\"@example.com!".to_vec(),
+            replacement: "".to_string(),
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(
+            "4.3.2.1.5.5.5.0.0.8.1.e164.arpa. 3600 IN NAPTR 100 10 \"u\" \"E2U+sip\" \"!^.*$!sip:\\\"info\\\"@example.com!\" .",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn apl_and_https_record_presentation() {
+        let apl = DnsRecord::APL {
+            domain: "example.com".to_string(),
+            items: vec![
+                AplItem {
+                    family: 1,
+                    prefix: 21,
+                    negation: false,
+                    address: vec![192, 168, 32],
+                },
+                AplItem {
+                    family: 2,
+                    prefix: 32,
+                    negation: true,
+                    address: vec![0x20, 0x01, 0x0D, 0xB8],
+                },
+            ],
+            class: CLASS_IN,
+            ttl: 3600,
+        };
+        assert_eq!(
+            "example.com. 3600 IN APL 1:192.168.32.0/21 !2:2001:db8::/32",
+            apl.to_presentation()
+        );
+
+        let https = DnsRecord::HTTPS {
+            domain: "example.com".to_string(),
+            priority: 1,
+            target: "".to_string(),
+            params: vec![
+                SvcParam::Alpn(vec!["h2".to_string(), "h3".to_string()]),
+                SvcParam::Port(443),
+            ],
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        assert_eq!(
+            "example.com. 300 IN HTTPS 1 . alpn=h2,h3 port=443",
+            https.to_presentation()
+        );
+    }
+
+    #[test]
+    fn opt_record_presented_in_generic_format() {
+        let record = DnsRecord::OPT {
+            packet_len: 1232,
+            flags: 0x8000,
+            options: vec![EdnsOption::Unknown {
+                code: 0xFDE9,
+                data: vec![0xAB],
+            }],
+        };
+        assert_eq!(
+            ". 32768 CLASS1232 OPT \\# 5 FDE90001AB",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn wks_record_survives_round_trip_byte_for_byte() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x03, b'f', b'o', b'o', 0x00, // domain
+            0x00, 0x0B, // qtype WKS
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x08, // data length
+            0xC0, 0x00, 0x02, 0x01, // address
+            0x06, // protocol TCP
+            0x00, 0x00, 0x04, // bitmap with port 21 set
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let record = DnsRecord::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(true, matches!(record, DnsRecord::Unknown { qtype: 11, .. }));
+
+        let mut out_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        record.write(&mut out_buffer).unwrap();
+        assert_eq!(record_bytes.len(), out_buffer.pos());
+        assert_eq!(
+            true,
+            out_buffer
+                .get_range(0, record_bytes.len())
+                .is_ok_and(|val| val == record_bytes)
+        );
+    }
+
+    #[test]
+    fn obsolete_record_types_kept_as_unknown() {
+        // MD, MF, MB, MG, MR, NULL and MINFO
+        for qtype in [3, 4, 7, 8, 9, 10, 14] {
+            let record = DnsRecord::Unknown {
+                domain: "foo".to_string(),
+                qtype,
+                data_len: 3,
+                data: vec![0x01, b'a', 0x00],
+                class: CLASS_IN,
+                ttl: 3600,
+            };
+            assert_eq!(record, round_trip(&record));
+        }
+    }
+
+    #[test]
+    fn return_error_if_a_record_data_length_longer_than_address() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x03, b'f', b'o', b'o', 0x00, // domain
+            0x00, 0x01, // qtype A
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x06, // data length
+            0xC0, 0x00, 0x02, 0x01, 0x00, 0x00, // address and two stray bytes
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str =
+            "Invalid record, record data doesn't match data length: qtype=A, data length=6, read=4";
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_mx_record_data_runs_past_data_length() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x03, b'f', b'o', b'o', 0x00, // domain
+            0x00, 0x0F, // qtype MX
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x03, // data length
+            0x00, 0x0A, // priority
+            0x04, b'm', b'a', b'i', b'l', 0x00, // host
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str = "Invalid record, record data doesn't match data length: qtype=MX, data length=3, read=8";
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_apl_item_runs_past_data_length() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x03, b'f', b'o', b'o', 0x00, // domain
+            0x00, 0x2A, // qtype APL
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x06, // data length
+            0x00, 0x01, 0x15, 0x03, 0xC0, 0xA8, 0x20, // item one byte longer than the data
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str =
+            "Invalid record data, item runs past end of record data: pos=15, item length=7, end=21";
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_https_param_runs_past_data_length() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // domain
+            0x00, 0x41, // qtype
+            0x00, 0x01, // class
+            0x00, 0x00, 0x0E, 0x10, // ttl
+            0x00, 0x08, // data length
+            0x00, 0x01, // priority
+            0x00, // target
+            0x00, 0x03, 0x00, 0x02, 0x01, 0xBB, // port=443, overruns data length
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str =
+            "Invalid record data, item runs past end of record data: pos=26, item length=6, end=31";
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_opt_option_runs_past_data_length() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x00, // root domain
+            0x00, 0x29, // qtype
+            0x04, 0xD0, // UDP payload size
+            0x00, 0x00, 0x00, 0x00, // extended rcode, version, flags
+            0x00, 0x06, // data length
+            0x00, 0x03, 0x00, 0x04, 0x61, 0x62, 0x63, 0x64, // nsid, overruns data length
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str =
+            "Invalid record data, item runs past end of record data: pos=11, item length=8, end=17";
+        assert_eq!(
+            true,
+            DnsRecord::from_buffer(&mut packet_buffer)
+                .is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn srv_record_survives_round_trip_with_uncompressed_target() {
+        let record = DnsRecord::SRV {
+            domain: "_sip._tcp.example.com".to_string(),
+            priority: 10,
+            weight: 60,
+            port: 5060,
+            target: "sip.example.com".to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        let mut packet = DnsPacket::new();
+        packet.questions.push(DnsQuestion::new(
+            "_sip._tcp.example.com".to_string(),
+            QueryType::SRV,
+        ));
+        packet.answers.push(record.clone());
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write_compressed(&mut packet_buffer).unwrap();
+        let target_bytes = b"\x03sip\x07example\x03com\x00";
+        let len = packet_buffer.pos();
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, len)
+                .unwrap()
+                .windows(target_bytes.len())
+                .any(|window| window == target_bytes)
+        );
+        _ = packet_buffer.seek(0);
+        assert_eq!(
+            true,
+            DnsPacket::from_buffer(&mut packet_buffer).is_ok_and(|res| res.answers == vec![record])
+        );
+    }
+
+    #[test]
+    fn srv_record_presentation() {
+        let record = DnsRecord::SRV {
+            domain: "_sip._tcp.example.com".to_string(),
+            priority: 10,
+            weight: 60,
+            port: 5060,
+            target: "sip.example.com".to_string(),
+            class: CLASS_IN,
+            ttl: 300,
+        };
+        assert_eq!(
+            "_sip._tcp.example.com. 300 IN SRV 10 60 5060 sip.example.com.",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn binary_txt_record_written_back_byte_for_byte() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let record_bytes = [
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, // domain
+            0x00, 0x10, // qtype
+            0x00, 0x01, // class
+            0x00, 0x00, 0x01, 0x2C, // ttl
+            0x00, 0x03, // data length
+            0x02, 0xFF, 0xFE, // string that isn't UTF-8
+        ];
+        buf[..record_bytes.len()].copy_from_slice(&record_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let record = DnsRecord::from_buffer(&mut packet_buffer).unwrap();
+        assert_eq!(
+            "example.com. 300 IN TXT \"\\255\\254\"",
+            record.to_presentation()
+        );
+
+        let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        record.write(&mut packet_buffer).unwrap();
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, packet_buffer.pos())
+                .is_ok_and(|val| val == record_bytes)
+        );
+    }
+
+    #[test]
+    fn record_class_survives_round_trip() {
+        let record = DnsRecord::TXT {
+            domain: "version.bind".to_string(),
+            data: vec![b"9.18.0".to_vec()],
+            class: CLASS_CH,
+            ttl: 0,
+        };
+        let res = round_trip(&record);
+        assert_eq!(CLASS_CH, res.class());
+        assert_eq!(record, res);
+        assert_eq!(
+            "version.bind. 0 CH TXT \"9.18.0\"",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn unknown_class_presented_generically() {
+        let record = DnsRecord::A {
+            domain: "example.com".to_string(),
+            addr: Ipv4Addr::new(192, 0, 2, 1),
+            class: 42,
+            ttl: 300,
+        };
+        assert_eq!(
+            "example.com. 300 CLASS42 A 192.0.2.1",
+            record.to_presentation()
+        );
+    }
+
+    #[test]
+    fn name_in_zone_only_at_label_boundary() {
+        assert_eq!(true, in_zone("www.Example.com", "example.com."));
+        assert_eq!(true, in_zone("example.com", "example.com"));
+        assert_eq!(true, in_zone("example.com", ""));
+        assert_eq!(false, in_zone("notexample.com", "example.com"));
+        assert_eq!(false, in_zone("com", "example.com"));
+    }
+}
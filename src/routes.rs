@@ -0,0 +1,99 @@
+use std::net::SocketAddr;
+
+use crate::record::in_zone;
+
+/// Upstreams to forward queries to by the domain they're for, as in split-horizon setups where
+/// internal domains go to an internal resolver. A name is routed by the longest configured
+/// suffix it falls under, and to the default upstream when it falls under none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamRoutes {
+    default: SocketAddr,
+    routes: Vec<(String, SocketAddr)>,
+}
+
+impl UpstreamRoutes {
+    pub fn new(default: SocketAddr) -> UpstreamRoutes {
+        UpstreamRoutes {
+            default,
+            routes: Vec::new(),
+        }
+    }
+
+    /// Route names at or below `suffix` to `upstream`, replacing any route for the same suffix
+    pub fn add_route(&mut self, suffix: &str, upstream: SocketAddr) {
+        let suffix = suffix.trim_end_matches('.').to_ascii_lowercase();
+        match self
+            .routes
+            .iter_mut()
+            .find(|(existing, _)| *existing == suffix)
+        {
+            Some((_, existing_upstream)) => *existing_upstream = upstream,
+            None => self.routes.push((suffix, upstream)),
+        }
+    }
+
+    /// Upstream to forward a query for `name` to
+    pub fn upstream_for(&self, name: &str) -> SocketAddr {
+        self.routes
+            .iter()
+            .filter(|(suffix, _)| in_zone(name, suffix))
+            .max_by_key(|(suffix, _)| suffix.len())
+            .map_or(self.default, |(_, upstream)| *upstream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public_upstream() -> SocketAddr {
+        "9.9.9.9:53".parse().unwrap()
+    }
+
+    fn internal_upstream() -> SocketAddr {
+        "10.0.0.53:53".parse().unwrap()
+    }
+
+    #[test]
+    fn name_under_configured_suffix_routed_to_its_upstream() {
+        let mut routes = UpstreamRoutes::new(public_upstream());
+        routes.add_route("corp.internal", internal_upstream());
+        assert_eq!(internal_upstream(), routes.upstream_for("corp.internal"));
+        assert_eq!(
+            internal_upstream(),
+            routes.upstream_for("git.Corp.Internal.")
+        );
+    }
+
+    #[test]
+    fn other_names_routed_to_default_upstream() {
+        let mut routes = UpstreamRoutes::new(public_upstream());
+        routes.add_route("corp.internal", internal_upstream());
+        assert_eq!(public_upstream(), routes.upstream_for("example.com"));
+        assert_eq!(public_upstream(), routes.upstream_for("notcorp.internal"));
+    }
+
+    #[test]
+    fn longest_matching_suffix_wins() {
+        let lab_upstream: SocketAddr = "10.1.0.53:53".parse().unwrap();
+        let mut routes = UpstreamRoutes::new(public_upstream());
+        routes.add_route("lab.corp.internal", lab_upstream);
+        routes.add_route("corp.internal", internal_upstream());
+        assert_eq!(lab_upstream, routes.upstream_for("host.lab.corp.internal"));
+        assert_eq!(
+            internal_upstream(),
+            routes.upstream_for("host.corp.internal")
+        );
+    }
+
+    #[test]
+    fn route_for_same_suffix_replaced() {
+        let mut routes = UpstreamRoutes::new(public_upstream());
+        routes.add_route("corp.internal", public_upstream());
+        routes.add_route("corp.internal.", internal_upstream());
+        assert_eq!(
+            internal_upstream(),
+            routes.upstream_for("git.corp.internal")
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::packet::PacketBuffer;
+use crate::record::{character_string, check_item_fits};
+
+const PARAM_KEY_ALPN: u16 = 1;
+const PARAM_KEY_PORT: u16 = 3;
+const PARAM_KEY_IPV4_HINT: u16 = 4;
+const PARAM_KEY_IPV6_HINT: u16 = 6;
+
+/// Key-value pair in the rdata of an SVCB or HTTPS record (RFC 9460). Keys without a dedicated
+/// variant, such as ECH, are kept as their raw value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SvcParam {
+    Alpn(Vec<String>),
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    Unknown { key: u16, value: Vec<u8> },
+}
+
+impl SvcParam {
+    pub fn from_buffer(buffer: &mut PacketBuffer) -> Result<SvcParam, String> {
+        let key = buffer.read_u16()?;
+        let len = buffer.read_u16()? as usize;
+
+        match key {
+            PARAM_KEY_ALPN => {
+                let mut protocols = Vec::new();
+                let end = buffer.pos() + len;
+                while buffer.pos() < end {
+                    let protocol_len = buffer.read()? as usize;
+                    check_item_fits(buffer, protocol_len, end)?;
+                    let protocol = buffer.read_bytes(protocol_len)?;
+                    protocols.push(String::from_utf8_lossy(&protocol).into_owned());
+                }
+                Ok(SvcParam::Alpn(protocols))
+            }
+            PARAM_KEY_PORT => {
+                if len != 2 {
+                    let err_str = format!("Invalid SvcParam, port must be 2 bytes: len={}", len);
+                    return Err(err_str);
+                }
+                Ok(SvcParam::Port(buffer.read_u16()?))
+            }
+            PARAM_KEY_IPV4_HINT => {
+                if len == 0 || !len.is_multiple_of(4) {
+                    let err_str = format!(
+                        "Invalid SvcParam, ipv4hint must be a non-zero multiple of 4 bytes: len={}",
+                        len
+                    );
+                    return Err(err_str);
+                }
+                let mut addrs = Vec::new();
+                for _ in 0..len / 4 {
+                    addrs.push(Ipv4Addr::from(buffer.read_u32()?));
+                }
+                Ok(SvcParam::Ipv4Hint(addrs))
+            }
+            PARAM_KEY_IPV6_HINT => {
+                if len == 0 || !len.is_multiple_of(16) {
+                    let err_str = format!(
+                        "Invalid SvcParam, ipv6hint must be a non-zero multiple of 16 bytes: len={}",
+                        len
+                    );
+                    return Err(err_str);
+                }
+                let mut addrs = Vec::new();
+                for _ in 0..len / 16 {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(&buffer.read_bytes(16)?);
+                    addrs.push(Ipv6Addr::from(octets));
+                }
+                Ok(SvcParam::Ipv6Hint(addrs))
+            }
+            _ => {
+                let value = buffer.read_bytes(len)?;
+                Ok(SvcParam::Unknown { key, value })
+            }
+        }
+    }
+
+    pub fn write(&self, buffer: &mut PacketBuffer) -> Result<(), String> {
+        match *self {
+            SvcParam::Alpn(ref protocols) => {
+                let len: usize = protocols.iter().map(|protocol| 1 + protocol.len()).sum();
+                buffer.write_u16(PARAM_KEY_ALPN)?;
+                buffer.write_u16(len as u16)?;
+                for protocol in protocols {
+                    buffer.write(protocol.len() as u8)?;
+                    buffer.write_bytes(protocol.as_bytes())?;
+                }
+            }
+            SvcParam::Port(port) => {
+                buffer.write_u16(PARAM_KEY_PORT)?;
+                buffer.write_u16(2)?;
+                buffer.write_u16(port)?;
+            }
+            SvcParam::Ipv4Hint(ref addrs) => {
+                buffer.write_u16(PARAM_KEY_IPV4_HINT)?;
+                buffer.write_u16(4 * addrs.len() as u16)?;
+                for addr in addrs {
+                    buffer.write_u32(u32::from(*addr))?;
+                }
+            }
+            SvcParam::Ipv6Hint(ref addrs) => {
+                buffer.write_u16(PARAM_KEY_IPV6_HINT)?;
+                buffer.write_u16(16 * addrs.len() as u16)?;
+                for addr in addrs {
+                    buffer.write_bytes(&addr.octets())?;
+                }
+            }
+            SvcParam::Unknown { key, ref value } => {
+                buffer.write_u16(key)?;
+                buffer.write_u16(value.len() as u16)?;
+                buffer.write_bytes(value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Param as `key=value` in the presentation format of RFC 9460, with unknown keys written
+    /// as `keyNNNNN`
+    pub(crate) fn to_presentation(&self) -> String {
+        match *self {
+            SvcParam::Alpn(ref protocols) => format!("alpn={}", comma_separated(protocols)),
+            SvcParam::Port(port) => format!("port={}", port),
+            SvcParam::Ipv4Hint(ref addrs) => format!("ipv4hint={}", comma_separated(addrs)),
+            SvcParam::Ipv6Hint(ref addrs) => format!("ipv6hint={}", comma_separated(addrs)),
+            SvcParam::Unknown { key, ref value } if value.is_empty() => format!("key{}", key),
+            SvcParam::Unknown { key, ref value } => {
+                format!("key{}={}", key, character_string(value))
+            }
+        }
+    }
+}
+
+fn comma_separated<T: fmt::Display>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    values.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::PACKET_BYTES_LENGTH;
+
+    #[test]
+    fn parse_alpn_param_from_buffer() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let param_bytes = [
+            0x00, 0x01, // key
+            0x00, 0x06, // length
+            0x02, b'h', b'2', 0x02, b'h', b'3', // protocols
+        ];
+        buf[..param_bytes.len()].copy_from_slice(&param_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_param = SvcParam::Alpn(vec!["h2".to_string(), "h3".to_string()]);
+        assert_eq!(
+            true,
+            SvcParam::from_buffer(&mut packet_buffer).is_ok_and(|val| val == expected_param)
+        );
+        assert_eq!(param_bytes.len(), packet_buffer.pos());
+    }
+
+    #[test]
+    fn params_survive_round_trip() {
+        let params = [
+            SvcParam::Alpn(vec!["h2".to_string(), "h3".to_string()]),
+            SvcParam::Port(8443),
+            SvcParam::Ipv4Hint(vec![
+                Ipv4Addr::new(192, 0, 2, 1),
+                Ipv4Addr::new(192, 0, 2, 2),
+            ]),
+            SvcParam::Ipv6Hint(vec!["2001:db8::1".parse().unwrap()]),
+            SvcParam::Unknown {
+                key: 5,
+                value: vec![0xDE, 0xAD, 0xBE, 0xEF],
+            },
+        ];
+        for param in params {
+            let mut packet_buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+            param.write(&mut packet_buffer).unwrap();
+            _ = packet_buffer.seek(0);
+            assert_eq!(
+                true,
+                SvcParam::from_buffer(&mut packet_buffer).is_ok_and(|val| val == param)
+            );
+        }
+    }
+
+    #[test]
+    fn return_error_if_port_param_has_wrong_length() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let param_bytes = [0x00, 0x03, 0x00, 0x03, 0x01, 0xBB, 0x00];
+        buf[..param_bytes.len()].copy_from_slice(&param_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str = "Invalid SvcParam, port must be 2 bytes: len=3";
+        assert_eq!(
+            true,
+            SvcParam::from_buffer(&mut packet_buffer).is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+
+    #[test]
+    fn return_error_if_alpn_protocol_runs_past_value_length() {
+        let mut buf = [0; PACKET_BYTES_LENGTH];
+        let param_bytes = [
+            0x00, 0x01, // key
+            0x00, 0x04, // length
+            0x02, b'h', b'2', 0x02, b'h', b'3', // second protocol overruns length
+        ];
+        buf[..param_bytes.len()].copy_from_slice(&param_bytes);
+        let mut packet_buffer = PacketBuffer::new(buf);
+        let expected_str =
+            "Invalid record data, item runs past end of record data: pos=8, item length=2, end=8";
+        assert_eq!(
+            true,
+            SvcParam::from_buffer(&mut packet_buffer).is_err_and(|err_str| err_str == expected_str)
+        );
+    }
+}
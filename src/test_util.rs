@@ -0,0 +1,42 @@
+use crate::packet::{PacketBuffer, PACKET_BYTES_LENGTH};
+
+/// Build a buffer from a hex dump such as Wireshark's "hex stream" export. Whitespace between
+/// bytes is ignored, so dumps can be split across lines. Panics on malformed hex, as this is
+/// only for authoring test vectors.
+pub(crate) fn packet_from_hex(hex: &str) -> PacketBuffer {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    assert_eq!(0, digits.len() % 2, "hex dump has an odd number of digits");
+    assert!(
+        digits.len() / 2 <= PACKET_BYTES_LENGTH,
+        "hex dump is longer than the buffer"
+    );
+
+    let mut buf = [0; PACKET_BYTES_LENGTH];
+    for (i, pair) in digits.chunks(2).enumerate() {
+        let byte: String = pair.iter().collect();
+        buf[i] = u8::from_str_radix(&byte, 16).expect("hex dump contains a non-hex digit");
+    }
+    PacketBuffer::new(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_dump_parsed_into_buffer_ignoring_whitespace() {
+        let packet_buffer = packet_from_hex(
+            "\
+            0a0B 01
+            ff\t00
+            ",
+        );
+        let expected_bytes = [0x0A, 0x0B, 0x01, 0xFF, 0x00];
+        assert_eq!(
+            true,
+            packet_buffer
+                .get_range(0, expected_bytes.len())
+                .is_ok_and(|bytes| bytes == expected_bytes)
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use std::fmt;
+
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+
+use crate::header::DnsHeader;
+use crate::packet::{DnsPacket, PacketBuffer, PACKET_BYTES_LENGTH};
+use crate::question::DnsQuestion;
+use crate::record::{DnsRecord, CLASS_ANY};
+
+pub const ALGORITHM_HMAC_SHA256: &str = "hmac-sha256";
+const DEFAULT_FUDGE: u16 = 300;
+/// Offset of the additional record count within the header
+const ARCOUNT_POS: usize = 10;
+
+/// What a MAC covers besides the message it signs (RFC 8945 section 4.3)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TsigChain<'a> {
+    /// A request, whose MAC covers only itself
+    Request,
+    /// The first response to a request, whose MAC also covers the request's MAC
+    Response(&'a [u8]),
+    /// A later message of a multi-message response such as a zone transfer, whose MAC covers
+    /// the previous message's MAC and only the timers of its own TSIG
+    Continuation(&'a [u8]),
+}
+
+/// Shared secret for signing and verifying messages with HMAC-SHA256
+#[derive(Clone, PartialEq)]
+pub struct TsigKey {
+    pub name: String,
+    pub secret: Vec<u8>,
+}
+
+/// Leave the secret out so that logging a key doesn't leak it
+impl fmt::Debug for TsigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TsigKey")
+            .field("name", &self.name)
+            .field("algorithm", &ALGORITHM_HMAC_SHA256)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TsigKey {
+    pub fn new(name: String, secret: Vec<u8>) -> TsigKey {
+        TsigKey { name, secret }
+    }
+
+    /// Sign a packet by appending a TSIG record to its additional section, returning the MAC
+    /// for use in the chain of any following message
+    pub fn sign(
+        &self,
+        packet: &mut DnsPacket,
+        time_signed: u64,
+        chain: TsigChain,
+    ) -> Result<Vec<u8>, String> {
+        let mut buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut buffer)?;
+        let message = buffer.get_range(0, buffer.pos())?;
+
+        let mut tsig = DnsRecord::TSIG {
+            domain: self.name.clone(),
+            algorithm: ALGORITHM_HMAC_SHA256.to_string(),
+            time_signed,
+            fudge: DEFAULT_FUDGE,
+            mac: Vec::new(),
+            original_id: packet.header.id,
+            error: 0,
+            other_data: Vec::new(),
+        };
+        let digest = self
+            .mac(message, &tsig, chain)?
+            .finalize()
+            .into_bytes()
+            .to_vec();
+        if let DnsRecord::TSIG { ref mut mac, .. } = tsig {
+            *mac = digest.clone();
+        }
+        packet.resources.push(tsig);
+        Ok(digest)
+    }
+
+    /// Verify the TSIG record that ends a received message, returning its MAC for use in the
+    /// chain of any following message. `now` is in seconds since the Unix epoch.
+    pub fn verify(&self, message: &[u8], now: u64, chain: TsigChain) -> Result<Vec<u8>, String> {
+        let (tsig, tsig_pos) = find_tsig(message)?;
+        let DnsRecord::TSIG {
+            ref domain,
+            ref algorithm,
+            time_signed,
+            fudge,
+            ref mac,
+            original_id,
+            error,
+            ..
+        } = tsig
+        else {
+            return Err("Invalid TSIG, message isn't signed".to_string());
+        };
+
+        if !domain.eq_ignore_ascii_case(&self.name) {
+            let err_str = format!(
+                "Invalid TSIG, message signed with unknown key: key={}, expected key={}",
+                domain, self.name
+            );
+            return Err(err_str);
+        }
+        if !algorithm.eq_ignore_ascii_case(ALGORITHM_HMAC_SHA256) {
+            let err_str = format!(
+                "Invalid TSIG, unsupported algorithm: algorithm={}",
+                algorithm
+            );
+            return Err(err_str);
+        }
+        if error != 0 {
+            let err_str = format!("Invalid TSIG, signer reported error: error={}", error);
+            return Err(err_str);
+        }
+
+        let mut unsigned = message[..tsig_pos].to_vec();
+        unsigned[..2].copy_from_slice(&original_id.to_be_bytes());
+        let arcount = u16::from_be_bytes([unsigned[ARCOUNT_POS], unsigned[ARCOUNT_POS + 1]]);
+        unsigned[ARCOUNT_POS..ARCOUNT_POS + 2].copy_from_slice(&(arcount - 1).to_be_bytes());
+        if self
+            .mac(&unsigned, &tsig, chain)?
+            .verify_slice(mac)
+            .is_err()
+        {
+            let err_str = format!("Invalid TSIG, MAC doesn't match: key={}", domain);
+            return Err(err_str);
+        }
+
+        if now.abs_diff(time_signed) > fudge as u64 {
+            let err_str = format!(
+                "Invalid TSIG, time signed outside of fudge: time signed={}, now={}, fudge={}",
+                time_signed, now, fudge
+            );
+            return Err(err_str);
+        }
+
+        Ok(mac.clone())
+    }
+
+    /// Start a MAC over everything a TSIG covers, ready to be finalised or verified
+    fn mac(
+        &self,
+        message: &[u8],
+        tsig: &DnsRecord,
+        chain: TsigChain,
+    ) -> Result<Hmac<Sha256>, String> {
+        let DnsRecord::TSIG {
+            ref domain,
+            ref algorithm,
+            time_signed,
+            fudge,
+            error,
+            ref other_data,
+            ..
+        } = *tsig
+        else {
+            return Err("Invalid TSIG, record to sign isn't a TSIG".to_string());
+        };
+
+        let mut hmac = match Hmac::<Sha256>::new_from_slice(&self.secret) {
+            Ok(hmac) => hmac,
+            Err(e) => return Err(format!("Invalid TSIG key: key={}, error={}", self.name, e)),
+        };
+        if let TsigChain::Response(prior_mac) | TsigChain::Continuation(prior_mac) = chain {
+            hmac.update(&(prior_mac.len() as u16).to_be_bytes());
+            hmac.update(prior_mac);
+        }
+        hmac.update(message);
+
+        let mut buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        if !matches!(chain, TsigChain::Continuation(_)) {
+            buffer.write_qname(&domain.to_ascii_lowercase())?;
+            buffer.write_u16(CLASS_ANY)?;
+            buffer.write_u32(0)?;
+            buffer.write_qname(&algorithm.to_ascii_lowercase())?;
+        }
+        buffer.write_u16((time_signed >> 32) as u16)?;
+        buffer.write_u32(time_signed as u32)?;
+        buffer.write_u16(fudge)?;
+        if !matches!(chain, TsigChain::Continuation(_)) {
+            buffer.write_u16(error)?;
+            buffer.write_u16(other_data.len() as u16)?;
+            buffer.write_bytes(other_data)?;
+        }
+        hmac.update(buffer.get_range(0, buffer.pos())?);
+
+        Ok(hmac)
+    }
+}
+
+/// Parse the last record of a message, which must be its TSIG, along with where it starts
+fn find_tsig(message: &[u8]) -> Result<(DnsRecord, usize), String> {
+    let mut buffer = PacketBuffer::from_message(message);
+    let header = DnsHeader::from_buffer(&mut buffer)?;
+    if header.resource_entries == 0 {
+        return Err("Invalid TSIG, message isn't signed".to_string());
+    }
+
+    for _ in 0..header.questions {
+        DnsQuestion::from_buffer(&mut buffer)?;
+    }
+    let records = header.answers as usize
+        + header.authoritative_entries as usize
+        + header.resource_entries as usize;
+    for _ in 0..records - 1 {
+        DnsRecord::from_buffer(&mut buffer)?;
+    }
+    let tsig_pos = buffer.pos();
+    let tsig = DnsRecord::from_buffer(&mut buffer)?;
+    if !matches!(tsig, DnsRecord::TSIG { .. }) {
+        return Err("Invalid TSIG, message isn't signed".to_string());
+    }
+
+    Ok((tsig, tsig_pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query_type::QueryType;
+
+    const TIME_SIGNED: u64 = 1_700_000_000;
+
+    fn key() -> TsigKey {
+        TsigKey::new("transfer-key".to_string(), b"0123456789abcdef".to_vec())
+    }
+
+    fn signed_query() -> Vec<u8> {
+        let mut packet = DnsPacket::new();
+        packet.header.id = 0xBEEF;
+        packet
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::AXFR));
+        key()
+            .sign(&mut packet, TIME_SIGNED, TsigChain::Request)
+            .unwrap();
+        let mut buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut buffer).unwrap();
+        buffer.get_range(0, buffer.pos()).unwrap().to_vec()
+    }
+
+    #[test]
+    fn debug_output_omits_secret() {
+        assert_eq!(
+            "TsigKey { name: \"transfer-key\", algorithm: \"hmac-sha256\", .. }",
+            format!("{:?}", key())
+        );
+    }
+
+    #[test]
+    fn signed_message_verifies_with_same_key() {
+        let res = key().verify(&signed_query(), TIME_SIGNED + 10, TsigChain::Request);
+        assert_eq!(true, res.is_ok_and(|mac| mac.len() == 32));
+    }
+
+    #[test]
+    fn known_message_has_expected_mac() {
+        let (tsig, _) = find_tsig(&signed_query()).unwrap();
+        // Computed separately from the digest layout in RFC 8945 section 4.3.3
+        let expected_mac = [
+            0xEC, 0xF9, 0x76, 0xFD, 0x9B, 0xC1, 0xC8, 0xD2, 0xE5, 0x8D, 0xCE, 0xFF, 0x14, 0xAB,
+            0x2E, 0x8E, 0x29, 0xC7, 0x8B, 0x05, 0xA7, 0xEC, 0x57, 0xEF, 0x6D, 0x45, 0x0F, 0xF6,
+            0x42, 0x8F, 0xE5, 0x35,
+        ];
+        assert_eq!(
+            true,
+            matches!(tsig, DnsRecord::TSIG { mac, .. } if mac == expected_mac)
+        );
+    }
+
+    #[test]
+    fn return_error_if_mac_tampered() {
+        let mut message = signed_query();
+        let last = message.len() - 7;
+        message[last] ^= 0x01;
+        let res = key().verify(&message, TIME_SIGNED, TsigChain::Request);
+        let expected_str = "Invalid TSIG, MAC doesn't match: key=transfer-key";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_message_tampered() {
+        let mut message = signed_query();
+        message[2] ^= 0x01;
+        let res = key().verify(&message, TIME_SIGNED, TsigChain::Request);
+        let expected_str = "Invalid TSIG, MAC doesn't match: key=transfer-key";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_signed_with_different_secret() {
+        let other_key = TsigKey::new("transfer-key".to_string(), b"not the secret".to_vec());
+        let res = other_key.verify(&signed_query(), TIME_SIGNED, TsigChain::Request);
+        let expected_str = "Invalid TSIG, MAC doesn't match: key=transfer-key";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_time_signed_outside_fudge() {
+        let now = TIME_SIGNED + DEFAULT_FUDGE as u64 + 1;
+        let res = key().verify(&signed_query(), now, TsigChain::Request);
+        let expected_str = format!(
+            "Invalid TSIG, time signed outside of fudge: time signed={}, now={}, fudge={}",
+            TIME_SIGNED, now, DEFAULT_FUDGE
+        );
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn return_error_if_message_unsigned() {
+        let mut packet = DnsPacket::new();
+        packet
+            .questions
+            .push(DnsQuestion::new("example.com".to_string(), QueryType::AXFR));
+        let mut buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut buffer).unwrap();
+        let message = buffer.get_range(0, buffer.pos()).unwrap();
+        let res = key().verify(message, TIME_SIGNED, TsigChain::Request);
+        let expected_str = "Invalid TSIG, message isn't signed";
+        assert_eq!(true, res.is_err_and(|err_str| err_str == expected_str));
+    }
+
+    #[test]
+    fn continuation_verifies_against_prior_mac() {
+        let mut packet = DnsPacket::new();
+        packet.header.response = true;
+        let prior_mac = vec![0x42; 32];
+        key()
+            .sign(
+                &mut packet,
+                TIME_SIGNED,
+                TsigChain::Continuation(&prior_mac),
+            )
+            .unwrap();
+        let mut buffer = PacketBuffer::new([0; PACKET_BYTES_LENGTH]);
+        packet.write(&mut buffer).unwrap();
+        let message = buffer.get_range(0, buffer.pos()).unwrap();
+
+        let res = key().verify(message, TIME_SIGNED, TsigChain::Continuation(&prior_mac));
+        assert_eq!(true, res.is_ok());
+        let res = key().verify(message, TIME_SIGNED, TsigChain::Response(&prior_mac));
+        assert_eq!(true, res.is_err());
+    }
+}